@@ -0,0 +1,272 @@
+use super::github_error::GitHubError;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// A GitHub App authenticates as itself with a short-lived JWT signed by the
+// app private key, and exchanges it for installation access tokens that act
+// on the repositories of the installation, valid for one hour.
+// See https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/authenticating-as-a-github-app-installation
+
+// Installation tokens are refreshed this long before they expire.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::minutes(5);
+
+// Auth is how the client authenticates its requests.
+#[derive(Clone)]
+pub enum Auth {
+    // a personal access token or the Actions GITHUB_TOKEN, no authentication if empty
+    Token(String),
+    // installation tokens of a GitHub App, shared by the clients of all repositories
+    App(Arc<AppAuth>),
+}
+
+impl Auth {
+    // token returns the bearer token for requests to the repository, if any.
+    pub async fn token(
+        &self,
+        api_url: &str,
+        owner: &str,
+        repo: &str,
+    ) -> Result<Option<String>, GitHubError> {
+        match self {
+            Auth::Token(token) if token.is_empty() => Ok(None),
+            Auth::Token(token) => Ok(Some(token.clone())),
+            Auth::App(app) => Ok(Some(
+                app.installation_token(api_url, owner, repo, Utc::now())
+                    .await?,
+            )),
+        }
+    }
+}
+
+pub struct AppAuth {
+    app_id: String,
+    key: jsonwebtoken::EncodingKey,
+    // installation to use for all repositories, looked up per repository owner if not set
+    installation_id: Option<u64>,
+    // installation tokens by repository owner
+    tokens: Mutex<HashMap<String, InstallationToken>>,
+}
+
+#[derive(Deserialize, Clone)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+impl AppAuth {
+    // new creates the app authentication from the PEM-encoded RSA private key of the app.
+    pub fn new(
+        app_id: &str,
+        private_key: &[u8],
+        installation_id: Option<u64>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = jsonwebtoken::EncodingKey::from_rsa_pem(private_key)
+            .map_err(|err| format!("invalid GitHub App private key: {}", err))?;
+        Ok(AppAuth {
+            app_id: app_id.to_string(),
+            key,
+            installation_id,
+            tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // jwt returns the token authenticating as the app. It is issued a minute in
+    // the past to allow for clock drift and expires after the maximum of 10 minutes.
+    fn jwt(&self, now: DateTime<Utc>) -> Result<String, GitHubError> {
+        let claims = Claims {
+            iat: (now - Duration::seconds(60)).timestamp(),
+            exp: (now + Duration::minutes(10)).timestamp(),
+            iss: self.app_id.clone(),
+        };
+        let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+        jsonwebtoken::encode(&header, &claims, &self.key)
+            .map_err(|err| GitHubError::Request(format!("cannot sign GitHub App JWT: {}", err)))
+    }
+
+    // installation_token returns a cached installation token for the repository owner,
+    // requesting a new one if there is none or it is about to expire.
+    async fn installation_token(
+        &self,
+        api_url: &str,
+        owner: &str,
+        repo: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String, GitHubError> {
+        let cached = self.tokens.lock().unwrap().get(owner).cloned();
+        if let Some(cached) = cached {
+            if cached.expires_at - TOKEN_REFRESH_MARGIN > now {
+                return Ok(cached.token);
+            }
+        }
+        let jwt = self.jwt(now)?;
+        let installation_id = match self.installation_id {
+            Some(id) => id,
+            None => {
+                let url = format!("{}/repos/{}/{}/installation", api_url, owner, repo);
+                let body = send(request(reqwest::Method::GET, &url, &jwt)).await?;
+                serde_json::from_str::<Installation>(&body)?.id
+            }
+        };
+        let url = format!(
+            "{}/app/installations/{}/access_tokens",
+            api_url, installation_id
+        );
+        let body = send(request(reqwest::Method::POST, &url, &jwt)).await?;
+        let token: InstallationToken = serde_json::from_str(&body)?;
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(owner.to_string(), token.clone());
+        Ok(token.token)
+    }
+}
+
+fn request(method: reqwest::Method, url: &str, jwt: &str) -> reqwest::RequestBuilder {
+    reqwest::Client::new()
+        .request(method, url)
+        .header("User-Agent", "rust/reqwest")
+        .header("Accept", "application/vnd.github+json")
+        .header("Authorization", format!("Bearer {}", jwt))
+}
+
+async fn send(req: reqwest::RequestBuilder) -> Result<String, GitHubError> {
+    let res = req.send().await?;
+    let status = res.status();
+    let body = res.text().await?;
+    if !status.is_success() {
+        let err = match GitHubError::from_response(status.as_u16(), &body, None) {
+            // without an installation token every request of the owner fails
+            GitHubError::Forbidden { message } => GitHubError::Auth {
+                status: 403,
+                message,
+            },
+            err => err,
+        };
+        return Err(err);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const PRIVATE_KEY: &str = include_str!("testdata/app-private-key.pem");
+    const PUBLIC_KEY: &str = include_str!("testdata/app-public-key.pem");
+
+    #[test]
+    fn test_jwt() {
+        let app = AppAuth::new("12345", PRIVATE_KEY.as_bytes(), None).unwrap();
+        let now = Utc::now();
+        let jwt = app.jwt(now).unwrap();
+
+        let key = jsonwebtoken::DecodingKey::from_rsa_pem(PUBLIC_KEY.as_bytes()).unwrap();
+        let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+        let claims = jsonwebtoken::decode::<Claims>(&jwt, &key, &validation)
+            .unwrap()
+            .claims;
+        assert_eq!(claims.iss, "12345");
+        assert_eq!(claims.iat, now.timestamp() - 60);
+        assert_eq!(claims.exp, now.timestamp() + 600);
+
+        assert!(AppAuth::new("12345", b"not a key", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_installation_token() {
+        let mock_server = MockServer::start().await;
+        let now = Utc::now();
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/installation"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"id": 42}"#))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        let expires_at = now + Duration::hours(1);
+        Mock::given(method("POST"))
+            .and(path("/app/installations/42/access_tokens"))
+            .and(header("Accept", "application/vnd.github+json"))
+            .respond_with(ResponseTemplate::new(201).set_body_string(format!(
+                r#"{{"token": "ghs_token", "expires_at": "{}"}}"#,
+                expires_at.to_rfc3339()
+            )))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let api_url = mock_server.uri();
+        let app = AppAuth::new("12345", PRIVATE_KEY.as_bytes(), None).unwrap();
+        let token = app
+            .installation_token(&api_url, "yurishkuro", "rustybot", now)
+            .await
+            .unwrap();
+        assert_eq!(token, "ghs_token");
+
+        // the token is cached until shortly before it expires
+        let later = now + Duration::minutes(50);
+        let token = app
+            .installation_token(&api_url, "yurishkuro", "rustybot", later)
+            .await
+            .unwrap();
+        assert_eq!(token, "ghs_token");
+        let expiring = now + Duration::minutes(56);
+        app.installation_token(&api_url, "yurishkuro", "rustybot", expiring)
+            .await
+            .unwrap();
+
+        // a configured installation is used without looking it up
+        let app = AppAuth::new("12345", PRIVATE_KEY.as_bytes(), Some(7)).unwrap();
+        let err = app
+            .installation_token(&api_url, "yurishkuro", "rustybot", now)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GitHubError::NotFound { .. }), "{}", err);
+
+        // a token the app is not allowed to create fails every request of the owner
+        Mock::given(method("POST"))
+            .and(path("/app/installations/8/access_tokens"))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .set_body_string(r#"{"message": "This installation has been suspended"}"#),
+            )
+            .mount(&mock_server)
+            .await;
+        let app = AppAuth::new("12345", PRIVATE_KEY.as_bytes(), Some(8)).unwrap();
+        let err = app
+            .installation_token(&api_url, "yurishkuro", "rustybot", now)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, GitHubError::Auth { status: 403, .. }),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_token() {
+        let auth = Auth::Token(String::new());
+        assert_eq!(auth.token("", "o", "r").await.unwrap(), None);
+        let auth = Auth::Token("secret".to_string());
+        assert_eq!(
+            auth.token("", "o", "r").await.unwrap().as_deref(),
+            Some("secret")
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use super::config::Backend;
+use clap::{ArgAction, Parser, Subcommand};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "rustybot", long_about = None)]
+pub struct Args {
+    /// Repository to manage, in the form owner/name
+    #[arg(long, global = true, env = "GITHUB_REPOSITORY")]
+    pub repo: Option<Repo>,
+
+    /// Path to the state machine configuration file
+    #[arg(long, global = true, default_value = "issue-manager.yml")]
+    pub config: String,
+
+    /// Path of the configuration file in the managed repository, e.g.
+    /// .github/issue-manager.yml, read from its default branch (or from the
+    /// .github repository of its owner) instead of --config
+    #[arg(long, global = true, env = "RUSTYBOT_REPO_CONFIG")]
+    pub repo_config: Option<String>,
+
+    /// Base URL of the GitHub REST API
+    #[arg(
+        long,
+        global = true,
+        env = "GITHUB_API_URL",
+        default_value = "https://api.github.com"
+    )]
+    pub api_url: String,
+
+    /// Login of the account the bot acts as, its own actions are not counted as activity
+    #[arg(long, global = true, default_value = "github-actions[bot]")]
+    pub bot_login: String,
+
+    /// Longest time in seconds to wait for the GitHub API rate limit to reset
+    /// before giving up
+    #[arg(long, global = true, default_value_t = 300)]
+    pub max_rate_limit_wait: u64,
+
+    /// Directory where responses are cached between runs and revalidated with
+    /// conditional requests, which do not count against the rate limit
+    #[arg(long, global = true, env = "RUSTYBOT_CACHE_DIR")]
+    pub cache_dir: Option<String>,
+
+    /// GitHub API used to read issues, overrides the `backend` of the configuration [default: rest]
+    #[arg(long, global = true, env = "RUSTYBOT_BACKEND", value_enum)]
+    pub backend: Option<Backend>,
+
+    /// ID of the GitHub App to authenticate as, instead of GITHUB_TOKEN
+    #[arg(
+        long,
+        global = true,
+        env = "GITHUB_APP_ID",
+        requires = "app_private_key"
+    )]
+    pub app_id: Option<String>,
+
+    /// Path to the PEM private key of the GitHub App
+    #[arg(long, global = true, env = "GITHUB_APP_PRIVATE_KEY_PATH")]
+    pub app_private_key: Option<String>,
+
+    /// Installation of the GitHub App to use, looked up by repository owner if not set
+    #[arg(long, global = true, env = "GITHUB_APP_INSTALLATION_ID")]
+    pub app_installation_id: Option<u64>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Evaluate open issues and apply the actions of the transitions that fire
+    Run {
+        /// Print the planned transitions without applying any actions
+        #[arg(
+            long,
+            action = ArgAction::Set,
+            num_args = 0..=1,
+            require_equals = true,
+            default_value_t = false,
+            default_missing_value = "true"
+        )]
+        dry_run: bool,
+
+        #[command(flatten)]
+        options: RunOptions,
+    },
+    /// Print the planned transitions without applying any actions (same as run --dry-run)
+    Plan {
+        #[command(flatten)]
+        options: RunOptions,
+    },
+    /// Validate the configuration file
+    Validate,
+    /// Show how the state machine evaluates a single issue
+    Explain {
+        /// Issue number
+        issue: u32,
+    },
+    /// Run an HTTP server that evaluates the issues affected by GitHub webhook events
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0:8080")]
+        listen: String,
+
+        /// Secret used to verify the X-Hub-Signature-256 header of webhook deliveries
+        #[arg(long, env = "WEBHOOK_SECRET", hide_env_values = true)]
+        webhook_secret: String,
+
+        /// Print the planned transitions without applying any actions
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl Args {
+    // cache_dir returns the directory of the response cache, if any. An empty
+    // value, like the default `cache-dir` input of action.yml, disables the cache.
+    pub fn cache_dir(&self) -> Option<&str> {
+        self.cache_dir.as_deref().filter(|dir| !dir.is_empty())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RunOptions {
+    /// Maximum number of issues to evaluate, 0 means no limit
+    #[arg(long, default_value_t = 0)]
+    pub max_issues: usize,
+
+    /// Name of the event that triggered the run; for issue, comment and pull request
+    /// events only the affected issue is evaluated
+    #[arg(long, env = "GITHUB_EVENT_NAME", requires = "event_path")]
+    pub event_name: Option<String>,
+
+    /// Path to the JSON payload of the event that triggered the run
+    #[arg(long, env = "GITHUB_EVENT_PATH")]
+    pub event_path: Option<String>,
+}
+
+// Repo identifies a GitHub repository as owner/name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Repo {
+    pub owner: String,
+    pub name: String,
+}
+
+impl std::str::FromStr for Repo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+                Ok(Repo {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                })
+            }
+            _ => Err(format!("expecting owner/name, got '{}'", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Repo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_parse() {
+        let repo: Repo = "yurishkuro/rustybot".parse().unwrap();
+        assert_eq!(repo.owner, "yurishkuro");
+        assert_eq!(repo.name, "rustybot");
+        assert_eq!(repo.to_string(), "yurishkuro/rustybot");
+
+        for invalid in ["rustybot", "/rustybot", "yurishkuro/", "a/b/c"] {
+            assert!(invalid.parse::<Repo>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::try_parse_from([
+            "rustybot",
+            "explain",
+            "42",
+            "--repo",
+            "yurishkuro/rustybot",
+            "--config",
+            ".github/issue-manager.yml",
+        ])
+        .unwrap();
+        assert_eq!(args.config, ".github/issue-manager.yml");
+        assert_eq!(args.repo.unwrap().name, "rustybot");
+        assert!(matches!(args.command, Command::Explain { issue: 42 }));
+
+        let args = Args::try_parse_from(["rustybot", "run", "--dry-run"]).unwrap();
+        assert!(matches!(args.command, Command::Run { dry_run: true, .. }));
+
+        // the form used by action.yml
+        let args =
+            Args::try_parse_from(["rustybot", "run", "--dry-run=false", "--max-issues", "10"])
+                .unwrap();
+        let Command::Run { dry_run, options } = args.command else {
+            panic!("expecting run command");
+        };
+        assert!(!dry_run);
+        assert_eq!(options.max_issues, 10);
+
+        let args = Args::try_parse_from(["rustybot", "plan", "--backend", "graphql"]).unwrap();
+        assert_eq!(args.backend, Some(Backend::Graphql));
+        let args = Args::try_parse_from([
+            "rustybot",
+            "serve",
+            "--webhook-secret",
+            "secret",
+            "--repo-config",
+            ".github/bot.yml",
+        ])
+        .unwrap();
+        assert_eq!(args.repo_config.as_deref(), Some(".github/bot.yml"));
+        let args = Args::try_parse_from(["rustybot", "plan", "--cache-dir", ".cache"]).unwrap();
+        assert_eq!(args.cache_dir(), Some(".cache"));
+        let args = Args::try_parse_from(["rustybot", "plan"]).unwrap();
+        assert!(matches!(args.command, Command::Plan { options } if options.max_issues == 0));
+
+        assert!(Args::try_parse_from(["rustybot", "--repo", "bad", "plan"]).is_err());
+        assert!(Args::try_parse_from(["rustybot", "--app-id", "1", "plan"]).is_err());
+
+        let args =
+            Args::try_parse_from(["rustybot", "serve", "--webhook-secret", "secret"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Command::Serve { listen, dry_run: false, .. } if listen == "0.0.0.0:8080"
+        ));
+    }
+
+    #[test]
+    fn test_empty_cache_dir() {
+        // action.yml sets RUSTYBOT_CACHE_DIR to the empty default of its input
+        std::env::set_var("RUSTYBOT_CACHE_DIR", "");
+        let args = Args::try_parse_from(["rustybot", "plan"]);
+        std::env::remove_var("RUSTYBOT_CACHE_DIR");
+        let args = args.unwrap();
+        assert_eq!(args.cache_dir.as_deref(), Some(""));
+        assert_eq!(args.cache_dir(), None);
+    }
+}
@@ -0,0 +1,72 @@
+// Commands are typed by maintainers in issue comments, one per line, either as
+// a slash command that may be followed by arguments (`/in-progress @someone`),
+// or as a bare word on a line of its own (`needs-info`). Quoted lines and code
+// blocks are ignored, so replying to a comment does not repeat its commands.
+
+// parse_commands returns the commands found in the comment body, without the
+// leading slash.
+pub fn parse_commands(body: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    let mut in_code_block = false;
+    for line in body.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block || line.starts_with('>') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        if let Some(command) = first.strip_prefix('/') {
+            if !command.is_empty() {
+                commands.push(command);
+            }
+        } else if words.next().is_none() {
+            commands.push(first);
+        }
+    }
+    commands
+}
+
+// has_command returns true if the comment body contains the command.
+// The command may be configured with or without the leading slash.
+pub fn has_command(body: &str, command: &str) -> bool {
+    let command = command.strip_prefix('/').unwrap_or(command);
+    parse_commands(body).contains(&command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        let body = "Thanks for the report!\n\
+                    \n\
+                    needs-info\n\
+                    /in-progress @yurishkuro\n\
+                    > /close\n\
+                    ```\n\
+                    /wontfix\n\
+                    ```\n\
+                    please provide more details\n\
+                    /\n";
+        assert_eq!(parse_commands(body), vec!["needs-info", "in-progress"]);
+        assert!(parse_commands("").is_empty());
+    }
+
+    #[test]
+    fn test_has_command() {
+        assert!(has_command("needs-info", "needs-info"));
+        assert!(has_command("/needs-info", "needs-info"));
+        assert!(has_command("  /in-progress  ", "/in-progress"));
+        assert!(has_command("I'll take it.\r\n/in-progress", "/in-progress"));
+        assert!(!has_command("needs-info is not needed", "needs-info"));
+        assert!(!has_command("/in-progress-later", "/in-progress"));
+        assert!(!has_command("> needs-info", "needs-info"));
+    }
+}
@@ -1,8 +1,47 @@
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StateMachine {
     pub states: Vec<State>,
+    // pull_requests defines a separate set of states for pull requests.
+    // Pull requests are not managed unless this section is present.
+    #[serde(
+        default,
+        rename = "pull-requests",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pull_requests: Option<PullRequests>,
+    // backend selects the API used to read issues, unless set with --backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PullRequests {
+    pub states: Vec<State>,
+}
+
+// Backend selects the GitHub API used to read issues. Updates always use the REST API.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// REST API, with requests per issue for its comments and timeline
+    Rest,
+    /// GraphQL API, fetching comments and timelines together with the issues
+    Graphql,
+}
+
+impl StateMachine {
+    // states_for returns the states applicable to the given issue,
+    // which depend on whether the issue is a pull request.
+    pub fn states_for(&self, issue: &super::github::Issue) -> &[State] {
+        match (&self.pull_requests, issue.is_pull_request()) {
+            (Some(pull_requests), true) => &pull_requests.states,
+            (None, true) => &[],
+            (_, false) => &self.states,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -14,6 +53,10 @@ pub struct State {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Transition {
+    // id identifies the transition when a configuration extending this one
+    // overrides it, instead of its description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub description: String,
     pub conditions: Vec<Condition>,
     pub actions: Vec<Action>,
@@ -21,11 +64,31 @@ pub struct Transition {
 
 #[derive(Debug)]
 pub enum Condition {
-    Activity,        // issue was updated
-    Command(String), // maintainer typed a given command in the comments
-    Label(String),   // issue has a given label
-    PullRequest,     // issue has a pull request attached resolving it
-    Timeout(u16),    // issue was not updated for given number of days
+    // Activity holds if anyone other than the bot itself commented, edited a comment
+    // or otherwise changed the issue since it entered its current state.
+    Activity,
+    // Command holds if a user with at least triage permission typed the command
+    // in a comment since the issue entered its current state, either as a slash
+    // command (`/in-progress`) or as a word on a line of its own (`needs-info`).
+    Command(String),
+    Label(String), // issue has a given label
+    // PullRequest holds if the issue has a linked pull request in the given state,
+    // linked either via closing keywords in the pull request description
+    // (e.g. `Fixes #12`) or via the development sidebar.
+    PullRequest(PullRequestState),
+    // Timeout holds after the given number of days since the last activity or
+    // since the issue entered its current state, whichever is later. If the
+    // transition also has label conditions, the days are counted from when
+    // those labels were applied instead, so timeouts can be chained.
+    Timeout(u16),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PullRequestState {
+    #[default]
+    Open,
+    Merged,
 }
 
 #[derive(Debug)]
@@ -37,6 +100,31 @@ pub enum Action {
     RemoveLabel(String),
 }
 
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Condition::Activity => write!(f, "activity"),
+            Condition::Command(command) => write!(f, "command {}", command),
+            Condition::Label(label) => write!(f, "label {}", label),
+            Condition::PullRequest(PullRequestState::Open) => write!(f, "pull-request"),
+            Condition::PullRequest(PullRequestState::Merged) => write!(f, "pull-request merged"),
+            Condition::Timeout(days) => write!(f, "timeout {} days", days),
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Action::AddLabel(label) => write!(f, "add-label {}", label),
+            Action::Close => write!(f, "close"),
+            Action::PostComment(comment) => write!(f, "post-comment {:?}", comment),
+            Action::ReplaceLabel(label) => write!(f, "replace-label {}", label),
+            Action::RemoveLabel(label) => write!(f, "remove-label {}", label),
+        }
+    }
+}
+
 // serde_helper defines structs that map to the config Schama,
 // where Condition and Action are designed as union types,
 // with a discriminating property `type`.
@@ -70,6 +158,8 @@ mod serde_helper {
         pub command: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         pub timeout: Option<u16>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub state: Option<super::PullRequestState>,
     }
 
     #[derive(Serialize, Deserialize, Debug, Default)]
@@ -103,14 +193,26 @@ impl<'de> serde::Deserialize<'de> for Condition {
     where
         D: serde::Deserializer<'de>,
     {
+        use serde::de::Error;
         use serde_helper::ConditionType;
         let condition: serde_helper::Condition = serde::Deserialize::deserialize(deserializer)?;
         match condition.condition_type {
             ConditionType::Activity => Ok(Condition::Activity),
-            ConditionType::Command => Ok(Condition::Command(condition.command.unwrap())),
-            ConditionType::Label => Ok(Condition::Label(condition.label.unwrap())),
-            ConditionType::PullRequest => Ok(Condition::PullRequest),
-            ConditionType::Timeout => Ok(Condition::Timeout(condition.timeout.unwrap())),
+            ConditionType::Command => condition
+                .command
+                .map(Condition::Command)
+                .ok_or_else(|| D::Error::missing_field("command")),
+            ConditionType::Label => condition
+                .label
+                .map(Condition::Label)
+                .ok_or_else(|| D::Error::missing_field("label")),
+            ConditionType::PullRequest => {
+                Ok(Condition::PullRequest(condition.state.unwrap_or_default()))
+            }
+            ConditionType::Timeout => condition
+                .timeout
+                .map(Condition::Timeout)
+                .ok_or_else(|| D::Error::missing_field("timeout")),
         }
     }
 }
@@ -145,21 +247,20 @@ impl serde::Serialize for Condition {
                 };
                 serde::Serialize::serialize(&condition, serializer)
             }
-            Condition::PullRequest => {
+            Condition::PullRequest(state) => {
+                // the default state is omitted
                 let condition = serde_helper::Condition {
                     condition_type: ConditionType::PullRequest,
-                    label: None,
-                    command: None,
-                    timeout: None,
+                    state: Some(*state).filter(|s| *s != PullRequestState::default()),
+                    ..Default::default()
                 };
                 serde::Serialize::serialize(&condition, serializer)
             }
             Condition::Timeout(timeout) => {
                 let condition = serde_helper::Condition {
                     condition_type: ConditionType::Timeout,
-                    label: None,
-                    command: None,
                     timeout: Some(*timeout),
+                    ..Default::default()
                 };
                 serde::Serialize::serialize(&condition, serializer)
             }
@@ -172,14 +273,25 @@ impl<'de> serde::Deserialize<'de> for Action {
     where
         D: serde::Deserializer<'de>,
     {
+        use serde::de::Error;
         use serde_helper::ActionType;
         let action: serde_helper::Action = serde::Deserialize::deserialize(deserializer)?;
+        let label = || {
+            action
+                .label
+                .clone()
+                .ok_or_else(|| D::Error::missing_field("label"))
+        };
         match action.action_type {
-            ActionType::AddLabel => Ok(Action::AddLabel(action.label.unwrap())),
+            ActionType::AddLabel => label().map(Action::AddLabel),
             ActionType::Close => Ok(Action::Close),
-            ActionType::PostComment => Ok(Action::PostComment(action.comment.unwrap())),
-            ActionType::ReplaceLabel => Ok(Action::ReplaceLabel(action.label.unwrap())),
-            ActionType::RemoveLabel => Ok(Action::RemoveLabel(action.label.unwrap())),
+            ActionType::PostComment => action
+                .comment
+                .clone()
+                .map(Action::PostComment)
+                .ok_or_else(|| D::Error::missing_field("comment")),
+            ActionType::ReplaceLabel => label().map(Action::ReplaceLabel),
+            ActionType::RemoveLabel => label().map(Action::RemoveLabel),
         }
     }
 }
@@ -260,11 +372,23 @@ mod tests {
         let deserialized: Condition = serde_json::from_str(&serialized).unwrap();
         assert!(matches!(deserialized, Condition::Label(ref s) if s == "test"));
 
-        let condition = Condition::PullRequest;
+        let condition = Condition::PullRequest(PullRequestState::Open);
         let serialized = serde_json::to_string(&condition).unwrap();
         assert_eq!(serialized, r#"{"type":"pull-request"}"#);
         let deserialized: Condition = serde_json::from_str(&serialized).unwrap();
-        assert!(matches!(deserialized, Condition::PullRequest));
+        assert!(matches!(
+            deserialized,
+            Condition::PullRequest(PullRequestState::Open)
+        ));
+
+        let condition = Condition::PullRequest(PullRequestState::Merged);
+        let serialized = serde_json::to_string(&condition).unwrap();
+        assert_eq!(serialized, r#"{"type":"pull-request","state":"merged"}"#);
+        let deserialized: Condition = serde_json::from_str(&serialized).unwrap();
+        assert!(matches!(
+            deserialized,
+            Condition::PullRequest(PullRequestState::Merged)
+        ));
 
         let condition = Condition::Timeout(10);
         let serialized = serde_json::to_string(&condition).unwrap();
@@ -304,5 +428,31 @@ mod tests {
         assert_eq!(serialized, r#"{"type":"remove-label","label":"test"}"#);
         let deserialized: Action = serde_json::from_str(&serialized).unwrap();
         assert!(matches!(deserialized, Action::RemoveLabel(ref s) if s == "test"));
+
+        // missing fields are errors rather than panics
+        let err = serde_json::from_str::<Action>(r#"{"type":"add-label"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "missing field `label`");
+        let err = serde_json::from_str::<Condition>(r#"{"type":"label"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "missing field `label`");
+        assert!(serde_json::from_str::<Condition>(r#"{"type":"timeout"}"#).is_err());
+        assert!(serde_json::from_str::<Action>(r#"{"type":"post-comment"}"#).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Condition::Activity.to_string(), "activity");
+        assert_eq!(
+            Condition::Command("/in-progress".into()).to_string(),
+            "command /in-progress"
+        );
+        assert_eq!(Condition::Timeout(30).to_string(), "timeout 30 days");
+        assert_eq!(
+            Action::ReplaceLabel("needs-info".into()).to_string(),
+            "replace-label needs-info"
+        );
+        assert_eq!(
+            Action::PostComment("Need \"more\" info.".into()).to_string(),
+            r#"post-comment "Need \"more\" info.""#
+        );
     }
 }
@@ -0,0 +1,576 @@
+use super::cli::Repo;
+use super::config_loader::ConfigError;
+use super::config_source::SourceMap;
+use super::config_validator::Severity;
+use super::github::GitHub;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+// A configuration can extend a base configuration with `extends`, so that
+// repositories share most of their state machine and only declare how they
+// differ from it. The base is a path relative to the extending file, or
+// `owner/name:path` for a file in the default branch of another repository.
+// A base can itself extend another configuration.
+//
+// The extending configuration is merged over its base:
+// - states are matched by label; the fields of a matching state replace those
+//   of the base state, except its transitions, which are merged
+// - transitions with an `id` are matched by id, and transitions without one by
+//   description; a matching transition replaces the base transition, keeping
+//   its position
+// - other states and transitions are appended after those of the base
+// Only the merged configuration has to be complete and valid, so overriding a
+// single transition only takes the label of its state and the transition.
+
+// Maximum number of configurations in a chain of `extends`.
+const MAX_EXTENDS: usize = 10;
+
+// RepoClients returns the client of a repository that configurations are read from.
+pub type RepoClients<'a> = &'a dyn Fn(&Repo) -> Arc<dyn GitHub>;
+
+// Origin is where a configuration file is read from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    File(PathBuf),
+    // a file in the default branch of a repository
+    Repo { repo: Repo, path: String },
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "{}", path.display()),
+            // e.g. `owner/repo:.github/issue-manager.yml`
+            Origin::Repo { repo, path } => write!(f, "{}:{}", repo, path),
+        }
+    }
+}
+
+// Document is the content of a configuration file.
+#[derive(Debug)]
+pub struct Document {
+    pub origin: Origin,
+    pub content: String,
+    // SHA of the blob of a file read from a repository
+    pub sha: Option<String>,
+}
+
+impl Document {
+    pub fn read(file_name: &str) -> Result<Document, Box<dyn std::error::Error>> {
+        Ok(Document {
+            origin: Origin::File(file_name.into()),
+            content: std::fs::read_to_string(file_name)?,
+            sha: None,
+        })
+    }
+
+    // extends returns the origin of the base configuration, if any. Paths are
+    // relative to the directory of the extending file, also in a repository,
+    // where paths starting with `/` are relative to its root.
+    // Documents that are not valid YAML extend nothing: their syntax errors are
+    // reported when the chain is loaded.
+    fn extends(&self) -> Result<Option<Origin>, Box<dyn std::error::Error>> {
+        let Ok(value) = serde_yaml::from_str::<Value>(&self.content) else {
+            return Ok(None);
+        };
+        let Some(extends) = value.get("extends") else {
+            return Ok(None);
+        };
+        // the schema validation of the merged configuration never sees `extends`
+        let Some(extends) = extends.as_str() else {
+            let diagnostic = SourceMap::new(&self.content).diagnostic(
+                &self.origin.to_string(),
+                "/extends",
+                format!("{} is not of type \"string\"", extends),
+                Severity::Error,
+            );
+            return Err(Box::new(ConfigError::SchemaValidation(vec![diagnostic])));
+        };
+        if let Some((repo, path)) = extends.split_once(':') {
+            let repo = repo.parse().map_err(|err| {
+                format!("{}: invalid extends `{}`: {}", self.origin, extends, err)
+            })?;
+            let path = path.trim_start_matches('/').to_string();
+            return Ok(Some(Origin::Repo { repo, path }));
+        }
+        Ok(Some(match &self.origin {
+            Origin::File(path) => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                Origin::File(normalize(&dir.join(extends)))
+            }
+            Origin::Repo { repo, path } => {
+                let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+                let path = normalize(&dir.join(extends));
+                Origin::Repo {
+                    repo: repo.clone(),
+                    path: path.to_string_lossy().trim_start_matches('/').to_string(),
+                }
+            }
+        }))
+    }
+}
+
+// normalize removes the `.` and `..` components of the path without reading the file
+// system, so that cycles through different paths to the same file are detected.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// resolve returns the document followed by the configurations it extends,
+// directly or not, in order.
+pub async fn resolve(
+    document: Document,
+    repos: RepoClients<'_>,
+) -> Result<Vec<Document>, Box<dyn std::error::Error>> {
+    let mut chain = vec![document];
+    loop {
+        let document = chain.last().unwrap();
+        let Some(base) = document.extends()? else {
+            return Ok(chain);
+        };
+        if chain.iter().any(|document| document.origin == base) {
+            let cycle: Vec<String> = chain.iter().map(|d| d.origin.to_string()).collect();
+            return Err(format!("cycle of extends: {} -> {}", cycle.join(" -> "), base).into());
+        }
+        if chain.len() == MAX_EXTENDS {
+            return Err(format!(
+                "{}: more than {} configurations extend each other",
+                chain[0].origin, MAX_EXTENDS
+            )
+            .into());
+        }
+        let base = fetch(base, repos).await.map_err(|err| {
+            format!(
+                "{}: cannot read extended configuration: {}",
+                document.origin, err
+            )
+        })?;
+        chain.push(base);
+    }
+}
+
+async fn fetch(
+    origin: Origin,
+    repos: RepoClients<'_>,
+) -> Result<Document, Box<dyn std::error::Error>> {
+    match &origin {
+        Origin::File(path) => {
+            let content =
+                std::fs::read_to_string(path).map_err(|err| format!("{}: {}", origin, err))?;
+            Ok(Document {
+                origin,
+                content,
+                sha: None,
+            })
+        }
+        Origin::Repo { repo, path } => {
+            let file = repos(repo)
+                .get_file(path)
+                .await?
+                .ok_or_else(|| format!("{} not found", origin))?;
+            Ok(Document {
+                origin,
+                content: file.content,
+                sha: Some(file.sha),
+            })
+        }
+    }
+}
+
+// Merged is the configuration merged from a chain of documents. It remembers
+// which document each part comes from, to locate problems in the files where
+// they can be fixed.
+pub struct Merged {
+    pub value: Value,
+    // index of the document and pointer in it of the merged parts, by their
+    // pointer in the merged configuration
+    origins: HashMap<String, (usize, String)>,
+}
+
+impl Merged {
+    // new merges the values of a chain of documents, the extending one first.
+    pub fn new(values: Vec<Value>) -> Self {
+        let mut merged = Merged {
+            value: Value::Null,
+            origins: HashMap::new(),
+        };
+        for (index, value) in values.into_iter().enumerate().rev() {
+            merged.extend(index, value);
+        }
+        merged
+    }
+
+    // origin returns the index of the document and the pointer in it of the
+    // node at the pointer, from its closest ancestor with a known origin.
+    pub fn origin(&self, pointer: &str) -> (usize, String) {
+        let mut prefix = pointer;
+        loop {
+            if let Some((index, origin)) = self.origins.get(prefix) {
+                return (*index, format!("{}{}", origin, &pointer[prefix.len()..]));
+            }
+            match prefix.rfind('/') {
+                Some(end) => prefix = &prefix[..end],
+                None => return (0, pointer.to_string()),
+            }
+        }
+    }
+
+    fn extend(&mut self, index: usize, mut value: Value) {
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("extends");
+        }
+        match value {
+            Value::Object(fields) if self.value.is_object() => {
+                for (key, field) in fields {
+                    match key.as_str() {
+                        "states" => self.merge_states("", index, field),
+                        "pull-requests" => self.merge_pull_requests(index, field),
+                        _ => self.replace("", &key, field, index, ""),
+                    }
+                }
+            }
+            // the last base, or a document that fails the schema validation
+            value => {
+                self.value = value;
+                self.record(String::new(), index, String::new());
+            }
+        }
+    }
+
+    fn merge_pull_requests(&mut self, index: usize, value: Value) {
+        let base = self.value.get("pull-requests");
+        match value {
+            Value::Object(fields) if base.is_some_and(Value::is_object) => {
+                for (key, field) in fields {
+                    match key.as_str() {
+                        "states" => self.merge_states("/pull-requests", index, field),
+                        _ => self.replace("/pull-requests", &key, field, index, "/pull-requests"),
+                    }
+                }
+            }
+            value => self.replace("", "pull-requests", value, index, ""),
+        }
+    }
+
+    // merge_states merges the states of the section at the pointer, which is
+    // the same in the merged configuration and in the document.
+    fn merge_states(&mut self, section: &str, index: usize, value: Value) {
+        let pointer = format!("{}/states", section);
+        let base = self.value.pointer(&pointer);
+        let Value::Array(states) = value else {
+            return self.replace(section, "states", value, index, section);
+        };
+        if !base.is_some_and(Value::is_array) {
+            return self.replace(section, "states", Value::Array(states), index, section);
+        }
+        for (s, state) in states.into_iter().enumerate() {
+            let origin = format!("{}/{}", pointer, s);
+            let base = self
+                .value
+                .pointer_mut(&pointer)
+                .and_then(Value::as_array_mut);
+            let base = base.expect("states are an array");
+            let label = state.get("label").and_then(Value::as_str);
+            let position = label.and_then(|label| {
+                base.iter()
+                    .position(|base| base.get("label").and_then(Value::as_str) == Some(label))
+            });
+            match (position, state) {
+                (Some(position), Value::Object(fields)) => {
+                    let target = format!("{}/{}", pointer, position);
+                    for (key, field) in fields {
+                        match key.as_str() {
+                            "transitions" => self.merge_transitions(&target, index, &origin, field),
+                            _ => self.replace(&target, &key, field, index, &origin),
+                        }
+                    }
+                }
+                (_, state) => {
+                    base.push(state);
+                    let target = format!("{}/{}", pointer, base.len() - 1);
+                    self.record(target, index, origin);
+                }
+            }
+        }
+    }
+
+    // merge_transitions merges the transitions of the state at the pointer,
+    // which comes from the state at origin in the document.
+    fn merge_transitions(&mut self, state: &str, index: usize, origin: &str, value: Value) {
+        let pointer = format!("{}/transitions", state);
+        let base = self
+            .value
+            .pointer_mut(&pointer)
+            .and_then(Value::as_array_mut);
+        let (Some(base), Value::Array(transitions)) = (base, &value) else {
+            return self.replace(state, "transitions", value, index, origin);
+        };
+        let mut targets = Vec::new();
+        for transition in transitions {
+            let position = base
+                .iter()
+                .position(|base| same_transition(base, transition));
+            match position {
+                Some(position) => {
+                    base[position] = transition.clone();
+                    targets.push(position);
+                }
+                None => {
+                    base.push(transition.clone());
+                    targets.push(base.len() - 1);
+                }
+            }
+        }
+        for (t, target) in targets.into_iter().enumerate() {
+            let target = format!("{}/{}", pointer, target);
+            self.record(target, index, format!("{}/transitions/{}", origin, t));
+        }
+    }
+
+    // replace sets the field of the object at the pointer to a value from the
+    // object at origin in the document.
+    fn replace(&mut self, pointer: &str, key: &str, value: Value, index: usize, origin: &str) {
+        if let Some(fields) = self
+            .value
+            .pointer_mut(pointer)
+            .and_then(Value::as_object_mut)
+        {
+            fields.insert(key.to_string(), value);
+        }
+        let segment = key.replace('~', "~0").replace('/', "~1");
+        let target = format!("{}/{}", pointer, segment);
+        self.record(target, index, format!("{}/{}", origin, segment));
+    }
+
+    // record sets the origin of the node at the pointer, replacing the origins
+    // of the nodes it contained.
+    fn record(&mut self, pointer: String, index: usize, origin: String) {
+        let nested = format!("{}/", pointer);
+        self.origins.retain(|key, _| !key.starts_with(&nested));
+        self.origins.insert(pointer, (index, origin));
+    }
+}
+
+// same_transition returns whether the extending transition overrides the base one:
+// ids are compared with ids, and descriptions only if neither transition has an id.
+fn same_transition(base: &Value, transition: &Value) -> bool {
+    fn field<'a>(transition: &'a Value, name: &str) -> Option<&'a str> {
+        transition.get(name).and_then(Value::as_str)
+    }
+    match (field(base, "id"), field(transition, "id")) {
+        (Some(base_id), Some(id)) => base_id == id,
+        (None, None) => {
+            let description = field(transition, "description");
+            description.is_some() && field(base, "description") == description
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_github::FakeGitHub;
+    use crate::github::RepoFile;
+
+    fn yaml(content: &str) -> Value {
+        serde_yaml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = yaml(
+            r#"
+            states:
+              - description: "Issue is open"
+                label: "open"
+                transitions:
+                  - description: "Issue is stale"
+                    conditions: [{type: "timeout", timeout: 10}]
+                    actions: [{type: "add-label", label: "stale"}]
+                  - id: "resolve"
+                    description: "Issue is resolved"
+                    conditions: [{type: "label", label: "resolved"}]
+                    actions: [{type: "close"}]
+              - description: "Issue is stale"
+                label: "stale"
+                transitions: []
+            "#,
+        );
+        let extending = yaml(
+            r#"
+            extends: base.yml
+            states:
+              - label: "open"
+                transitions:
+                  - id: "resolve"
+                    description: "Issue is fixed"
+                    conditions: [{type: "label", label: "fixed"}]
+                    actions: [{type: "close"}]
+                  - description: "Issue is stale"
+                    conditions: [{type: "timeout", timeout: 30}]
+                    actions: [{type: "add-label", label: "stale"}]
+                  - description: "Issue is accepted"
+                    conditions: [{type: "command", command: "accept"}]
+                    actions: [{type: "replace-label", label: "accepted"}]
+                  # the base transition has an id, so it is not matched by description
+                  - description: "Issue is resolved"
+                    conditions: [{type: "command", command: "resolve"}]
+                    actions: [{type: "close"}]
+              - description: "Issue is accepted"
+                label: "accepted"
+                transitions: []
+            pull-requests:
+              states: []
+            "#,
+        );
+        let merged = Merged::new(vec![extending, base]);
+        let value = &merged.value;
+        assert!(value.get("extends").is_none());
+        let labels: Vec<&str> = value["states"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|state| state["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["open", "stale", "accepted"]);
+        let open = &value["states"][0];
+        assert_eq!(open["description"], "Issue is open");
+        let transitions: Vec<&str> = open["transitions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|transition| transition["description"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                "Issue is stale",
+                "Issue is fixed",
+                "Issue is accepted",
+                "Issue is resolved"
+            ]
+        );
+        assert_eq!(open["transitions"][0]["conditions"][0]["timeout"], 30);
+        assert_eq!(value["pull-requests"]["states"], Value::Array(vec![]));
+
+        let origin = |pointer| merged.origin(pointer);
+        let at = |index, pointer: &str| (index, pointer.to_string());
+        assert_eq!(origin("/states/1/label"), at(1, "/states/1/label"));
+        assert_eq!(
+            origin("/states/0/description"),
+            at(1, "/states/0/description")
+        );
+        assert_eq!(origin("/states/0/label"), at(0, "/states/0/label"));
+        assert_eq!(
+            origin("/states/0/transitions/1/actions/0"),
+            at(0, "/states/0/transitions/0/actions/0")
+        );
+        assert_eq!(
+            origin("/states/0/transitions/0"),
+            at(0, "/states/0/transitions/1")
+        );
+        assert_eq!(
+            origin("/states/2/transitions"),
+            at(0, "/states/1/transitions")
+        );
+        assert_eq!(
+            origin("/pull-requests/states"),
+            at(0, "/pull-requests/states")
+        );
+        assert_eq!(origin(""), at(1, ""));
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, content).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let mut org = FakeGitHub::default();
+        org.files.insert(
+            "base.yml".to_string(),
+            RepoFile {
+                path: "base.yml".to_string(),
+                sha: "b1".to_string(),
+                content: "states: []\n".to_string(),
+            },
+        );
+        let org: Arc<dyn GitHub> = Arc::new(org);
+        let repos = |repo: &Repo| {
+            assert_eq!(repo.to_string(), "yurishkuro/.github");
+            org.clone()
+        };
+
+        write("shared.yml", "extends: yurishkuro/.github:/base.yml\n");
+        let file_name = write(".github/issue-manager.yml", "extends: ../shared.yml\n");
+        let chain = resolve(Document::read(&file_name).unwrap(), &repos)
+            .await
+            .unwrap();
+        let origins: Vec<String> = chain.iter().map(|d| d.origin.to_string()).collect();
+        let shared = dir.path().join("shared.yml");
+        assert_eq!(
+            origins,
+            vec![
+                file_name.clone(),
+                shared.display().to_string(),
+                "yurishkuro/.github:base.yml".to_string(),
+            ]
+        );
+        assert_eq!(chain[2].sha.as_deref(), Some("b1"));
+
+        write("shared.yml", "extends: .github/issue-manager.yml\n");
+        let err = resolve(Document::read(&file_name).unwrap(), &repos)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("cycle of extends"), "{}", err);
+
+        write("shared.yml", "extends: yurishkuro/.github:missing.yml\n");
+        let err = resolve(Document::read(&file_name).unwrap(), &repos)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}: cannot read extended configuration: \
+                 yurishkuro/.github:missing.yml not found",
+                shared.display()
+            )
+        );
+
+        // the schema of `extends` is checked before resolving it
+        let file_name = write("invalid.yml", "extends: [base.yml]\nstates: []\n");
+        let err = resolve(Document::read(&file_name).unwrap(), &repos)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with(&format!(
+                "Schema validation errors:\n  - {}:1:10: [\"base.yml\"] is not of type \"string\" \
+                 (at /extends)",
+                file_name
+            )),
+            "{}",
+            err
+        );
+    }
+}
@@ -1,26 +1,57 @@
 use super::config;
+use super::config_extends::{self, Document, Merged, RepoClients};
+use super::config_source::{Diagnostic, Location, SourceMap};
+use super::config_validator::{self, Problem, Severity};
 
 #[derive(Debug)]
-enum ConfigError {
+pub enum ConfigError {
     SchemaLoading(String),
-    SchemaValidation(Vec<String>),
+    Syntax(Box<Diagnostic>),
+    SchemaValidation(Vec<Diagnostic>),
+    Invalid(Vec<Diagnostic>),
+}
+
+impl ConfigError {
+    // diagnostics returns the located errors, if any.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            ConfigError::SchemaLoading(_) => &[],
+            ConfigError::Syntax(diagnostic) => std::slice::from_ref(diagnostic.as_ref()),
+            ConfigError::SchemaValidation(errs) | ConfigError::Invalid(errs) => errs,
+        }
+    }
 }
 
 impl std::fmt::Display for ConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             ConfigError::SchemaLoading(msg) => write!(f, "Schema loading error: {}", msg),
+            ConfigError::Syntax(diagnostic) => write!(f, "Syntax error: {}", diagnostic),
             ConfigError::SchemaValidation(errs) => {
                 write!(f, "Schema validation errors:")?;
-                for err in errs {
-                    write!(f, "\n  - {}", err)?;
-                }
-                Ok(())
+                write_list(f, errs)
+            }
+            ConfigError::Invalid(errs) => {
+                write!(f, "Configuration errors:")?;
+                write_list(f, errs)
             }
         }
     }
 }
 
+// write_list writes the diagnostics as a list, indenting their snippets.
+fn write_list(f: &mut std::fmt::Formatter, diagnostics: &[Diagnostic]) -> std::fmt::Result {
+    for diagnostic in diagnostics {
+        let text = diagnostic.to_string();
+        let mut lines = text.lines();
+        write!(f, "\n  - {}", lines.next().unwrap_or_default())?;
+        for line in lines {
+            write!(f, "\n    {}", line)?;
+        }
+    }
+    Ok(())
+}
+
 impl std::error::Error for ConfigError {}
 
 const SCHEMA_BYTES: &[u8] = include_bytes!("config-schema.json");
@@ -36,45 +67,159 @@ fn load_schema(schema_bytes: &[u8]) -> Result<jsonschema::JSONSchema, ConfigErro
             ConfigError::SchemaLoading(err.to_string())
         })?;
 
-    return JSONSchema::options()
+    JSONSchema::options()
         .with_draft(Draft::Draft7)
         .compile(&schema_json)
         .map_err(|err| -> ConfigError {
             println!("Schema Compile Error: {}", err);
             ConfigError::SchemaLoading(err.to_string())
+        })
+}
+
+// Sources locates the problems of a merged configuration in the documents its parts come from.
+struct Sources<'a> {
+    documents: &'a [Document],
+    maps: Vec<SourceMap<'a>>,
+    merged: &'a Merged,
+}
+
+impl Sources<'_> {
+    fn diagnostic(&self, pointer: &str, message: String, severity: Severity) -> Diagnostic {
+        let (index, pointer) = self.merged.origin(pointer);
+        let file_name = self.documents[index].origin.to_string();
+        self.maps[index].diagnostic(&file_name, &pointer, message, severity)
+    }
+
+    fn diagnostics(&self, problems: Vec<Problem>) -> Vec<Diagnostic> {
+        problems
+            .into_iter()
+            .map(|problem| self.diagnostic(&problem.pointer(), problem.message, problem.severity))
+            .collect()
+    }
+}
+
+// parse_value parses a document of the chain, reporting its syntax errors.
+fn parse_value(document: &Document, source: &SourceMap) -> Result<serde_json::Value, ConfigError> {
+    serde_yaml::from_str(&document.content).map_err(|err| {
+        let location = err.location().map(|location| Location {
+            line: location.line(),
+            column: location.column(),
         });
+        ConfigError::Syntax(Box::new(source.diagnostic_at(
+            &document.origin.to_string(),
+            "",
+            location,
+            err.to_string(),
+            Severity::Error,
+        )))
+    })
 }
 
-fn validate_config(file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn validate_config(
+    yaml_value: &serde_json::Value,
+    sources: &Sources,
+) -> Result<(), Box<dyn std::error::Error>> {
     let schema = load_schema(SCHEMA_BYTES)?;
 
-    let yaml_content = std::fs::read_to_string(file_name)?;
-    let yaml_value: serde_json::Value = serde_yaml::from_str(&yaml_content)?;
-
-    Ok(schema.validate(&yaml_value).map_err(|errors| {
-        // let strings: Vec<String> = errors.map(|error| error.to_string()).collect();
+    schema.validate(yaml_value).map_err(|errors| {
         Box::new(ConfigError::SchemaValidation(
-            errors.map(|err| err.to_string()).collect(),
+            errors
+                .map(|err| {
+                    let pointer = err.instance_path.to_string();
+                    sources.diagnostic(&pointer, err.to_string(), Severity::Error)
+                })
+                .collect(),
         ))
-    })?)
+    })?;
+    let problems = config_validator::check_required_fields(yaml_value);
+    if !problems.is_empty() {
+        return Err(Box::new(ConfigError::Invalid(
+            sources.diagnostics(problems),
+        )));
+    }
+    Ok(())
+}
+
+// load_config loads the configuration file, merged with the configurations it extends.
+pub async fn load_config(
+    file_name: &str,
+    repos: RepoClients<'_>,
+) -> Result<config::StateMachine, Box<dyn std::error::Error>> {
+    let documents = config_extends::resolve(Document::read(file_name)?, repos).await?;
+    load_documents(&documents)
 }
 
-pub fn load_config(file_name: &str) -> Result<config::StateMachine, Box<dyn std::error::Error>> {
-    use serde_yaml;
-    validate_config(file_name)?;
-    let yaml_content = std::fs::read_to_string(file_name)?;
-    let config: config::StateMachine = serde_yaml::from_str(&yaml_content)?;
+// load_documents validates and parses the configuration merged from a chain of
+// documents returned by config_extends::resolve. Warnings are printed.
+pub fn load_documents(
+    documents: &[Document],
+) -> Result<config::StateMachine, Box<dyn std::error::Error>> {
+    let (config, warnings) = check_documents(documents)?;
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
     Ok(config)
 }
 
+// check_config loads the configuration and returns it with the warnings found by the
+// semantic validation, or ConfigError with the located errors if it is invalid.
+pub async fn check_config(
+    file_name: &str,
+    repos: RepoClients<'_>,
+) -> Result<(config::StateMachine, Vec<Diagnostic>), Box<dyn std::error::Error>> {
+    let documents = config_extends::resolve(Document::read(file_name)?, repos).await?;
+    check_documents(&documents)
+}
+
+// check_documents validates the configuration merged from a chain of documents, the
+// extending one first. Only the merged configuration is validated, and problems are
+// located in the document that the offending part comes from.
+fn check_documents(
+    documents: &[Document],
+) -> Result<(config::StateMachine, Vec<Diagnostic>), Box<dyn std::error::Error>> {
+    use serde::Deserialize;
+    let maps: Vec<SourceMap> = documents
+        .iter()
+        .map(|document| SourceMap::new(&document.content))
+        .collect();
+    let values = documents
+        .iter()
+        .zip(&maps)
+        .map(|(document, map)| parse_value(document, map))
+        .collect::<Result<Vec<_>, _>>()?;
+    let merged = Merged::new(values);
+    let sources = Sources {
+        documents,
+        maps,
+        merged: &merged,
+    };
+    validate_config(&merged.value, &sources)?;
+    let config = config::StateMachine::deserialize(&merged.value)?;
+    let (errors, warnings): (Vec<Problem>, Vec<Problem>) = config_validator::check(&config)
+        .into_iter()
+        .partition(|problem| problem.severity == Severity::Error);
+    if !errors.is_empty() {
+        return Err(Box::new(ConfigError::Invalid(sources.diagnostics(errors))));
+    }
+    Ok((config, sources.diagnostics(warnings)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::Repo;
+    use crate::fake_github::FakeGitHub;
+    use crate::github::GitHub;
     use std::io::Write;
+    use std::sync::Arc;
     use tempfile::NamedTempFile;
 
-    #[test]
-    fn test_load_config() {
+    fn no_repos(_: &Repo) -> Arc<dyn GitHub> {
+        Arc::new(FakeGitHub::default())
+    }
+
+    #[tokio::test]
+    async fn test_load_config() {
         let yaml = r#"
         states:
           - description: "Issue is open"
@@ -107,14 +252,53 @@ mod tests {
         file.write_all(yaml.as_bytes()).unwrap();
         file.flush().unwrap();
 
-        let config = load_config(file.path().to_str().unwrap()).unwrap();
+        let config = load_config(file.path().to_str().unwrap(), &no_repos)
+            .await
+            .unwrap();
         assert!(config.states.len() == 2);
         assert!(config.states[0].transitions.len() == 2);
         assert!(config.states[1].transitions.len() == 1);
+        assert!(config.pull_requests.is_none());
+        assert!(config.backend.is_none());
     }
 
-    #[test]
-    fn test_invalid_config() {
+    #[tokio::test]
+    async fn test_load_config_pull_requests() {
+        let yaml = r#"
+        states: []
+        backend: graphql
+        pull-requests:
+          states:
+            - description: "Waiting for review"
+              label: "needs-review"
+              transitions:
+                - description: "Review requested changes"
+                  conditions:
+                    - type: "label"
+                      label: "changes-requested"
+                  actions:
+                    - type: "replace-label"
+                      label: "needs-changes"
+            - description: "Waiting for changes"
+              label: "needs-changes"
+              transitions: []
+        "#;
+        let mut file = NamedTempFile::new().expect("Failed to create temporary file");
+        file.write_all(yaml.as_bytes()).unwrap();
+        file.flush().unwrap();
+
+        let config = load_config(file.path().to_str().unwrap(), &no_repos)
+            .await
+            .unwrap();
+        assert!(config.states.is_empty());
+        assert_eq!(config.backend, Some(crate::config::Backend::Graphql));
+        let pull_requests = config.pull_requests.unwrap();
+        assert_eq!(pull_requests.states.len(), 2);
+        assert_eq!(pull_requests.states[0].label, "needs-review");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config() {
         let yaml = r#"
         states:
           - description: "Issue is open"
@@ -131,10 +315,166 @@ mod tests {
         file.write_all(yaml.as_bytes()).unwrap();
         file.flush().unwrap();
 
-        let config = load_config(file.path().to_str().unwrap());
+        let file_name = file.path().to_str().unwrap();
+        let config = load_config(file_name, &no_repos).await;
         assert!(config.is_err());
         let mut expected = String::from("Schema validation errors:\n");
-        expected.push_str(r#"  - "type" is a required property"#);
+        expected.push_str(&format!(
+            r#"  - {}:8:21: "type" is a required property (at /states/0/transitions/0/conditions/0)
+      |
+    8 |                   - timeout: 10
+      |                     ^"#,
+            file_name
+        ));
         assert_eq!(expected, config.unwrap_err().to_string());
     }
+
+    #[tokio::test]
+    async fn test_semantic_errors() {
+        let yaml = r#"
+        states:
+          - description: "Issue is open"
+            label: "open"
+            transitions:
+              - description: "Label is missing"
+                conditions:
+                  - type: "label"
+                actions:
+                  - type: "replace-label"
+                    label: "closed"
+        "#;
+        let mut file = NamedTempFile::new().expect("Failed to create temporary file");
+        file.write_all(yaml.as_bytes()).unwrap();
+        file.flush().unwrap();
+        let file_name = file.path().to_str().unwrap();
+        let err = load_config(file_name, &no_repos).await.unwrap_err();
+        assert!(
+            err.to_string().starts_with(&format!(
+                "Configuration errors:\n  - {}:8:21: `label` requires field `label` \
+                 (at /states/0/transitions/0/conditions/0)",
+                file_name
+            )),
+            "{}",
+            err
+        );
+
+        let yaml = yaml.replace(r#"type: "label""#, r#"type: "activity""#);
+        let mut file = NamedTempFile::new().expect("Failed to create temporary file");
+        file.write_all(yaml.as_bytes()).unwrap();
+        file.flush().unwrap();
+        let file_name = file.path().to_str().unwrap();
+        let err = load_config(file_name, &no_repos).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                r#"Configuration errors:
+  - {}:11:28: replace-label target `closed` is not a declared state (at /states/0/transitions/0/actions/0/label)
+       |
+    11 |                     label: "closed"
+       |                            ^"#,
+                file_name
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_config_extends() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_name = dir.path().join("base.yml");
+        std::fs::write(
+            &base_name,
+            r#"
+states:
+  - description: "Waiting for triage"
+    label: "needs-triage"
+    transitions:
+      - id: "accept"
+        description: "Accepted by a maintainer"
+        conditions:
+          - type: "command"
+            command: "accept"
+        actions:
+          - type: "remove-label"
+            label: "needs-triage"
+"#,
+        )
+        .unwrap();
+        let file_name = dir.path().join("issue-manager.yml");
+        let file_name = file_name.to_str().unwrap();
+        let extending = r#"
+extends: base.yml
+states:
+  - label: "needs-triage"
+    transitions:
+      - id: "accept"
+        description: "Accepted by a maintainer"
+        conditions:
+          - type: "command"
+            command: "accept"
+        actions:
+          - type: "replace-label"
+            label: "accepted"
+"#;
+        std::fs::write(file_name, extending).unwrap();
+        let err = load_config(file_name, &no_repos).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                r#"Configuration errors:
+  - {}:13:20: replace-label target `accepted` is not a declared state (at /states/0/transitions/0/actions/0/label)
+       |
+    13 |             label: "accepted"
+       |                    ^"#,
+                file_name
+            )
+        );
+
+        let extending = format!(
+            "{}{}",
+            extending,
+            r#"
+  - description: "Accepted"
+    label: "accepted"
+    transitions:
+      - description: "Missing its conditions"
+        actions: []
+"#
+        );
+        std::fs::write(file_name, &extending).unwrap();
+        let err = load_config(file_name, &no_repos).await.unwrap_err();
+        assert!(
+            err.to_string().starts_with(&format!(
+                "Schema validation errors:\n  - {}:18:9: \"conditions\" is a required property \
+                 (at /states/1/transitions/0)",
+                file_name
+            )),
+            "{}",
+            err
+        );
+
+        let extending = extending.replace("actions: []", "conditions: []\n        actions: []");
+        std::fs::write(file_name, &extending).unwrap();
+        let config = load_config(file_name, &no_repos).await.unwrap();
+        assert_eq!(config.states.len(), 2);
+        assert_eq!(config.states[0].description, "Waiting for triage");
+        let transition = &config.states[0].transitions[0];
+        assert_eq!(transition.id.as_deref(), Some("accept"));
+        assert_eq!(transition.actions[0].to_string(), "replace-label accepted");
+
+        // problems in the base are located in the base
+        let base = std::fs::read_to_string(&base_name).unwrap();
+        std::fs::write(&base_name, base.replace("remove-label", "remove-labels")).unwrap();
+        std::fs::write(
+            file_name,
+            extending.replace("id: \"accept\"", "id: \"other\""),
+        )
+        .unwrap();
+        let err = load_config(file_name, &no_repos).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("{}:12:19:", base_name.display())),
+            "{}",
+            err
+        );
+    }
 }
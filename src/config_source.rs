@@ -0,0 +1,348 @@
+use super::config_validator::Severity;
+use std::collections::HashMap;
+use yaml_rust2::parser::{MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+use yaml_rust2::Event;
+
+// serde_yaml does not keep the positions of the nodes it parses, so the
+// configuration is parsed a second time with a YAML event parser to find
+// where each node starts, keyed by its JSON pointer, e.g.
+// `/states/0/transitions/1/actions/0`.
+
+// Location is a position in the source file, both 1-based.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+// SourceMap finds the location of the nodes of a YAML document.
+pub struct SourceMap<'a> {
+    source: &'a str,
+    locations: HashMap<String, Location>,
+}
+
+impl<'a> SourceMap<'a> {
+    // new parses the document, which is expected to be valid YAML since it was
+    // already parsed by serde_yaml. Nodes after a syntax error have no location.
+    pub fn new(source: &'a str) -> Self {
+        let mut locator = Locator::default();
+        let _ = Parser::new_from_str(source).load(&mut locator, false);
+        SourceMap {
+            source,
+            locations: locator.locations,
+        }
+    }
+
+    // locate returns the location of the node at the JSON pointer, or of its
+    // closest ancestor if the node does not exist, e.g. a missing field.
+    pub fn locate(&self, pointer: &str) -> Option<Location> {
+        let mut pointer = pointer;
+        loop {
+            if let Some(location) = self.locations.get(pointer) {
+                return Some(*location);
+            }
+            pointer = &pointer[..pointer.rfind('/')?];
+        }
+    }
+
+    // line returns the text of the line, without the line break.
+    pub fn line(&self, line: usize) -> Option<&'a str> {
+        self.source.lines().nth(line.checked_sub(1)?)
+    }
+
+    // diagnostic locates the message about the node at the JSON pointer.
+    pub fn diagnostic(
+        &self,
+        file: &str,
+        pointer: &str,
+        message: String,
+        severity: Severity,
+    ) -> Diagnostic {
+        let location = self.locate(pointer);
+        self.diagnostic_at(file, pointer, location, message, severity)
+    }
+
+    // diagnostic_at creates a diagnostic at a known location, e.g. of a syntax error.
+    pub fn diagnostic_at(
+        &self,
+        file: &str,
+        pointer: &str,
+        location: Option<Location>,
+        message: String,
+        severity: Severity,
+    ) -> Diagnostic {
+        Diagnostic {
+            file: file.to_string(),
+            pointer: pointer.to_string(),
+            location,
+            message,
+            severity,
+            source_line: location
+                .and_then(|location| self.line(location.line))
+                .map(str::to_string),
+        }
+    }
+}
+
+// Diagnostic is an error or warning about a node of the configuration file.
+// It is displayed with its location and the source line, with a caret under
+// the start of the node:
+//
+//   issue-manager.yml:8:13: "type" is a required property (at /states/0/transitions/0/conditions/0)
+//     |
+//   8 |           - timeout: 10
+//     |             ^
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub pointer: String,
+    pub location: Option<Location>,
+    pub message: String,
+    pub severity: Severity,
+    source_line: Option<String>,
+}
+
+impl Diagnostic {
+    // workflow_command returns the GitHub Actions workflow command that shows the
+    // diagnostic as an annotation on its line in the pull request that changed it.
+    // See https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+    pub fn workflow_command(&self) -> String {
+        let command = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut properties = format!("file={}", escape_property(&self.file));
+        if let Some(location) = self.location {
+            properties.push_str(&format!(",line={},col={}", location.line, location.column));
+        }
+        let mut message = self.message.clone();
+        if !self.pointer.is_empty() {
+            message.push_str(&format!(" (at {})", self.pointer));
+        }
+        format!(
+            "::{} {},title=rustybot configuration::{}",
+            command,
+            properties,
+            escape_data(&message)
+        )
+    }
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(location) = self.location {
+            write!(f, ":{}:{}", location.line, location.column)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.pointer.is_empty() {
+            write!(f, " (at {})", self.pointer)?;
+        }
+        let (Some(location), Some(source_line)) = (self.location, &self.source_line) else {
+            return Ok(());
+        };
+        let gutter = " ".repeat(location.line.to_string().len());
+        write!(f, "\n{} |", gutter)?;
+        write!(f, "\n{} | {}", location.line, source_line)?;
+        write!(f, "\n{} | {}^", gutter, " ".repeat(location.column - 1))
+    }
+}
+
+// Frame is a collection being parsed, with the key or index of its current entry.
+enum Frame {
+    Sequence(usize),
+    // the key of the current entry, None while the key is expected
+    Mapping(Option<String>),
+}
+
+impl Frame {
+    fn segment(&self) -> String {
+        match self {
+            Frame::Sequence(index) => index.to_string(),
+            Frame::Mapping(key) => {
+                let key = key.as_deref().unwrap_or_default();
+                key.replace('~', "~0").replace('/', "~1")
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Locator {
+    stack: Vec<Frame>,
+    locations: HashMap<String, Location>,
+}
+
+impl Locator {
+    // pointer returns the JSON pointer of the current entry of the collection
+    // at the given depth of the stack, or of the document root for depth 0.
+    fn pointer(&self, depth: usize) -> String {
+        let segments = self.stack[..depth].iter().map(Frame::segment);
+        segments.map(|segment| format!("/{}", segment)).collect()
+    }
+
+    // start_node records the location of a node starting at the marker.
+    fn start_node(&mut self, mark: Marker) {
+        self.record(self.pointer(self.stack.len()), mark);
+    }
+
+    fn record(&mut self, pointer: String, mark: Marker) {
+        let location = Location {
+            line: mark.line(),
+            column: mark.col() + 1,
+        };
+        self.locations.entry(pointer).or_insert(location);
+    }
+
+    // end_node moves the parent collection to its next entry.
+    fn end_node(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Sequence(index)) => *index += 1,
+            Some(Frame::Mapping(key)) => *key = None,
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for Locator {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Mapping(key @ None)) = self.stack.last_mut() {
+                    *key = Some(value);
+                    // block mappings start at their first key rather than at the
+                    // marker of the mapping start, which is after the key
+                    self.record(self.pointer(self.stack.len() - 1), mark);
+                    return;
+                }
+                self.start_node(mark);
+                self.end_node();
+            }
+            Event::Alias(_) => {
+                self.start_node(mark);
+                self.end_node();
+            }
+            Event::SequenceStart(..) => {
+                self.start_node(mark);
+                self.stack.push(Frame::Sequence(0));
+            }
+            Event::MappingStart(..) => {
+                self.stack.push(Frame::Mapping(None));
+            }
+            Event::MappingEnd => {
+                // empty mappings, located at their end
+                self.record(self.pointer(self.stack.len() - 1), mark);
+                self.stack.pop();
+                self.end_node();
+            }
+            Event::SequenceEnd => {
+                self.stack.pop();
+                self.end_node();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let yaml = "\
+states:
+  - description: 'Issue is open'
+    label: open
+    transitions:
+      - description: stale
+        conditions:
+          - timeout: 10
+        actions: []
+";
+        let map = SourceMap::new(yaml);
+        let at = |line, column| Some(Location { line, column });
+        assert_eq!(map.locate("/states/0/label"), at(3, 12));
+        assert_eq!(map.locate("/states/0"), at(2, 5));
+        assert_eq!(
+            map.locate("/states/0/transitions/0/conditions/0/timeout"),
+            at(7, 22)
+        );
+        assert_eq!(
+            map.locate("/states/0/transitions/0/conditions/0"),
+            at(7, 13)
+        );
+        // missing nodes are located at their parent
+        assert_eq!(
+            map.locate("/states/0/transitions/0/conditions/0/type"),
+            at(7, 13)
+        );
+        assert_eq!(map.locate("/states/0/transitions/0/actions"), at(8, 18));
+        assert_eq!(map.line(7), Some("          - timeout: 10"));
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let yaml = "states:\n  - label: open\n    transitions: []\n";
+        let map = SourceMap::new(yaml);
+        let diagnostic = map.diagnostic(
+            "issue-manager.yml",
+            "/states/0/description",
+            r#""description" is a required property"#.to_string(),
+            Severity::Error,
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            r#"issue-manager.yml:2:5: "description" is a required property (at /states/0/description)
+  |
+2 |   - label: open
+  |     ^"#
+        );
+
+        let map = SourceMap::new("");
+        let diagnostic = map.diagnostic("c.yml", "", "no states".to_string(), Severity::Error);
+        assert_eq!(diagnostic.to_string(), "c.yml: no states");
+    }
+
+    #[test]
+    fn test_workflow_command() {
+        let yaml = "states:\n  - label: open\n    transitions: []\n";
+        let map = SourceMap::new(yaml);
+        let diagnostic = map.diagnostic(
+            ".github/issue-manager.yml",
+            "/states/0",
+            "\"description\" is a required property\n100% sure".to_string(),
+            Severity::Error,
+        );
+        assert_eq!(
+            diagnostic.workflow_command(),
+            "::error file=.github/issue-manager.yml,line=2,col=5,\
+             title=rustybot configuration::\
+             \"description\" is a required property%0A100%25 sure (at /states/0)"
+        );
+
+        let diagnostic = map.diagnostic_at(
+            "a,b:c.yml",
+            "",
+            None,
+            "unused".to_string(),
+            Severity::Warning,
+        );
+        assert_eq!(
+            diagnostic.workflow_command(),
+            "::warning file=a%2Cb%3Ac.yml,title=rustybot configuration::unused"
+        );
+    }
+}
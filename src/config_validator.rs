@@ -0,0 +1,399 @@
+use super::config::{Action, Condition, State, StateMachine};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// The JSON schema only checks the shape of the configuration. The checks here
+// catch configurations that are well-formed but cannot work as intended, and
+// report them with the YAML path of the offending entry,
+// e.g. `states[0].transitions[1].actions[0]`.
+
+// Problem is a semantic error or warning in the configuration.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+    pub severity: Severity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    // the configuration is rejected
+    Error,
+    // the configuration is loaded, but probably does not do what was intended
+    Warning,
+}
+
+impl Problem {
+    fn error(path: String, message: String) -> Self {
+        Problem {
+            path,
+            message,
+            severity: Severity::Error,
+        }
+    }
+
+    fn warning(path: String, message: String) -> Self {
+        Problem {
+            path,
+            message,
+            severity: Severity::Warning,
+        }
+    }
+}
+
+impl Problem {
+    // pointer returns the JSON pointer of the path,
+    // e.g. `/states/0/label` for `states[0].label`.
+    pub fn pointer(&self) -> String {
+        let path = self.path.replace('[', ".").replace(']', "");
+        path.split('.')
+            .map(|segment| format!("/{}", segment))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+// check_required_fields checks that every condition and action has the fields
+// its type needs. It runs on the raw document that passed the schema, since
+// such entries cannot be deserialized.
+pub fn check_required_fields(value: &Value) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let sections = [
+        ("states", value.get("states")),
+        (
+            "pull-requests.states",
+            value.get("pull-requests").and_then(|v| v.get("states")),
+        ),
+    ];
+    for (section, states) in sections {
+        let Some(states) = states.and_then(Value::as_array) else {
+            continue;
+        };
+        for (s, state) in states.iter().enumerate() {
+            let Some(transitions) = state.get("transitions").and_then(Value::as_array) else {
+                continue;
+            };
+            for (t, transition) in transitions.iter().enumerate() {
+                let path = format!("{}[{}].transitions[{}]", section, s, t);
+                for kind in ["conditions", "actions"] {
+                    let entries = transition.get(kind).and_then(Value::as_array);
+                    for (i, entry) in entries.into_iter().flatten().enumerate() {
+                        let entry_type = entry.get("type").and_then(Value::as_str).unwrap_or("");
+                        let Some(required) = required_field(kind, entry_type) else {
+                            continue;
+                        };
+                        if entry.get(required).is_none() {
+                            problems.push(Problem::error(
+                                format!("{}.{}[{}]", path, kind, i),
+                                format!("`{}` requires field `{}`", entry_type, required),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    problems
+}
+
+// required_field returns the field required by the type of a condition or action, if any.
+fn required_field(kind: &str, entry_type: &str) -> Option<&'static str> {
+    match (kind, entry_type) {
+        ("conditions", "command") => Some("command"),
+        ("conditions", "label") => Some("label"),
+        ("conditions", "timeout") => Some("timeout"),
+        ("actions", "add-label" | "replace-label" | "remove-label") => Some("label"),
+        ("actions", "post-comment") => Some("comment"),
+        _ => None,
+    }
+}
+
+// check validates the relations between states and transitions. Issues and
+// pull requests are checked separately, since they never share states.
+pub fn check(config: &StateMachine) -> Vec<Problem> {
+    let mut problems = check_states("states", &config.states);
+    if let Some(pull_requests) = &config.pull_requests {
+        problems.extend(check_states("pull-requests.states", &pull_requests.states));
+    }
+    problems
+}
+
+fn check_states(section: &str, states: &[State]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    // index of the first state with each label; an issue with several state
+    // labels is in the first of those states
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (s, state) in states.iter().enumerate() {
+        if let Some(first) = index.get(state.label.as_str()) {
+            problems.push(Problem::error(
+                format!("{}[{}].label", section, s),
+                format!(
+                    "duplicate state label `{}`, already used by {}[{}]",
+                    state.label, section, first
+                ),
+            ));
+        } else {
+            index.insert(&state.label, s);
+        }
+    }
+
+    // labels that transitions put issues into, besides the labels added manually
+    let mut entered: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (s, state) in states.iter().enumerate() {
+        problems.extend(check_transition_keys(&format!("{}[{}]", section, s), state));
+        for (t, transition) in state.transitions.iter().enumerate() {
+            let path = format!("{}[{}].transitions[{}]", section, s, t);
+            for (a, action) in transition.actions.iter().enumerate() {
+                match action {
+                    Action::ReplaceLabel(label) if !index.contains_key(label.as_str()) => {
+                        problems.push(Problem::error(
+                            format!("{}.actions[{}].label", path, a),
+                            format!("replace-label target `{}` is not a declared state", label),
+                        ));
+                    }
+                    Action::ReplaceLabel(label) | Action::AddLabel(label) => {
+                        entered.entry(label).or_default().push(s);
+                    }
+                    _ => {}
+                }
+            }
+            for (c, condition) in transition.conditions.iter().enumerate() {
+                let Condition::Label(label) = condition else {
+                    continue;
+                };
+                if let Some(&other) = index.get(label.as_str()).filter(|&&other| other < s) {
+                    problems.push(Problem::error(
+                        format!("{}.conditions[{}]", path, c),
+                        format!(
+                            "contradictory condition: an issue labeled `{}` is in state {}[{}], \
+                             not in `{}`",
+                            label, section, other, state.label
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    // the first state is where new issues start, e.g. with a label from an issue template
+    for (s, state) in states.iter().enumerate().skip(1) {
+        let from_other = entered
+            .get(state.label.as_str())
+            .is_some_and(|sources| sources.iter().any(|&source| source != s));
+        if !from_other && index.get(state.label.as_str()) == Some(&s) {
+            problems.push(Problem::warning(
+                format!("{}[{}]", section, s),
+                format!(
+                    "state `{}` is unreachable: no transition of another state adds its label",
+                    state.label
+                ),
+            ));
+        }
+    }
+    problems
+}
+
+// check_transition_keys reports transitions of the state that an extending
+// configuration cannot tell apart: ids must be unique, and transitions without
+// an id are told apart by their description.
+fn check_transition_keys(path: &str, state: &State) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    let mut descriptions: HashMap<&str, usize> = HashMap::new();
+    for (t, transition) in state.transitions.iter().enumerate() {
+        let transition_path = format!("{}.transitions[{}]", path, t);
+        match &transition.id {
+            Some(id) => match ids.get(id.as_str()) {
+                Some(first) => problems.push(Problem::error(
+                    format!("{}.id", transition_path),
+                    format!(
+                        "duplicate transition id `{}`, already used by {}.transitions[{}]",
+                        id, path, first
+                    ),
+                )),
+                None => {
+                    ids.insert(id, t);
+                }
+            },
+            None => match descriptions.get(transition.description.as_str()) {
+                Some(first) => problems.push(Problem::warning(
+                    format!("{}.description", transition_path),
+                    format!(
+                        "duplicate transition description `{}`, already used by \
+                         {}.transitions[{}]; give the transitions an id",
+                        transition.description, path, first
+                    ),
+                )),
+                None => {
+                    descriptions.insert(&transition.description, t);
+                }
+            },
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(problems: &[Problem]) -> Vec<String> {
+        problems.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_required_fields() {
+        let yaml = r#"
+        states:
+          - description: "Issue is open"
+            label: "open"
+            transitions:
+              - description: "Fields are missing"
+                conditions:
+                  - type: "label"
+                  - type: "activity"
+                  - type: "timeout"
+                    label: "stale"
+                actions:
+                  - type: "close"
+                  - type: "post-comment"
+        pull-requests:
+          states:
+            - description: "Waiting for review"
+              label: "needs-review"
+              transitions:
+                - description: "Command is missing"
+                  conditions:
+                    - type: "command"
+                  actions: []
+        "#;
+        let value: Value = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            messages(&check_required_fields(&value)),
+            vec![
+                "states[0].transitions[0].conditions[0]: `label` requires field `label`",
+                "states[0].transitions[0].conditions[2]: `timeout` requires field `timeout`",
+                "states[0].transitions[0].actions[1]: `post-comment` requires field `comment`",
+                "pull-requests.states[0].transitions[0].conditions[0]: \
+                 `command` requires field `command`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for triage"
+            label: "needs-triage"
+            transitions:
+              - description: "Request more info"
+                conditions:
+                  - type: "command"
+                    command: "needs-info"
+                actions:
+                  - type: "replace-label"
+                    label: "needs-info"
+              - description: "Target is not a state"
+                conditions:
+                  - type: "command"
+                    command: "accept"
+                actions:
+                  - type: "replace-label"
+                    label: "accepted"
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions:
+              - description: "Never fires"
+                conditions:
+                  - type: "label"
+                    label: "needs-triage"
+                actions:
+                  - type: "close"
+              - description: "Stale"
+                conditions:
+                  - type: "label"
+                    label: "stale"
+                actions:
+                  - type: "close"
+          - description: "Nothing leads here"
+            label: "orphan"
+            transitions:
+              - description: "Back to triage"
+                conditions:
+                  - type: "activity"
+                actions:
+                  - type: "replace-label"
+                    label: "needs-triage"
+          - description: "Duplicate"
+            label: "needs-info"
+            transitions: []
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let problems = check(&config);
+        assert_eq!(
+            messages(&problems),
+            vec![
+                "states[3].label: duplicate state label `needs-info`, already used by states[1]",
+                "states[0].transitions[1].actions[0].label: \
+                 replace-label target `accepted` is not a declared state",
+                "states[1].transitions[0].conditions[0]: contradictory condition: \
+                 an issue labeled `needs-triage` is in state states[0], not in `needs-info`",
+                "states[2]: state `orphan` is unreachable: \
+                 no transition of another state adds its label",
+            ]
+        );
+        assert_eq!(problems[3].severity, Severity::Warning);
+        assert_eq!(
+            problems[1].pointer(),
+            "/states/0/transitions/1/actions/0/label"
+        );
+        assert!(problems[..3].iter().all(|p| p.severity == Severity::Error));
+
+        let config: StateMachine =
+            serde_yaml::from_str(include_str!("../issue-manager.yml")).unwrap();
+        assert_eq!(check(&config), vec![]);
+    }
+
+    #[test]
+    fn test_check_transition_keys() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions:
+              - id: "close"
+                description: "Close"
+                conditions: []
+                actions: []
+              - id: "close"
+                description: "Close again"
+                conditions: []
+                actions: []
+              - description: "Close"
+                conditions: []
+                actions: []
+              - description: "Close"
+                conditions: []
+                actions: []
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let problems = check(&config);
+        assert_eq!(
+            messages(&problems),
+            vec![
+                "states[0].transitions[1].id: \
+                 duplicate transition id `close`, already used by states[0].transitions[0]",
+                "states[0].transitions[3].description: duplicate transition description `Close`, \
+                 already used by states[0].transitions[2]; give the transitions an id",
+            ]
+        );
+        assert_eq!(problems[0].severity, Severity::Error);
+        assert_eq!(problems[1].severity, Severity::Warning);
+    }
+}
@@ -0,0 +1,765 @@
+use super::command;
+use super::config::{Action, Condition, PullRequestState, State, StateMachine, Transition};
+use super::github::{Comment, GitHub, Issue, IssueState, Permission, TimelineEvent};
+use super::github_error::GitHubError;
+use super::references;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+// Plan is the outcome of evaluating the state machine against an issue:
+// the state the issue is currently in and the transition that fired.
+#[derive(Debug)]
+pub struct Plan<'a> {
+    pub state: &'a State,
+    pub transition: &'a Transition,
+}
+
+impl Plan<'_> {
+    pub fn actions(&self) -> &[Action] {
+        &self.transition.actions
+    }
+}
+
+// MIN_COMMAND_PERMISSION is the permission required for commands in comments
+// to trigger transitions.
+pub const MIN_COMMAND_PERMISSION: Permission = Permission::Triage;
+
+// Context carries the data needed to evaluate conditions for an issue.
+pub struct Context<'a> {
+    pub issue: &'a Issue,
+    pub timeline: Vec<TimelineEvent>,
+    pub comments: Vec<Comment>,
+    // permissions of comment authors, keyed by login
+    pub permissions: HashMap<String, Permission>,
+    // bot_login is the account the bot acts as; its own actions are not activity.
+    pub bot_login: String,
+    pub now: DateTime<Utc>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(issue: &'a Issue, now: DateTime<Utc>) -> Self {
+        Context {
+            issue,
+            timeline: Vec::new(),
+            comments: Vec::new(),
+            permissions: HashMap::new(),
+            bot_login: String::new(),
+            now,
+        }
+    }
+
+    // load creates the context for the issue, fetching only the data needed
+    // by the conditions of the transitions of its current state.
+    pub async fn load(
+        gh: &dyn GitHub,
+        config: &StateMachine,
+        issue: &'a Issue,
+        now: DateTime<Utc>,
+        bot_login: &str,
+    ) -> Result<Context<'a>, GitHubError> {
+        let mut ctx = Context::new(issue, now);
+        ctx.bot_login = bot_login.to_string();
+        let Some(state) = find_state(config, issue) else {
+            return Ok(ctx);
+        };
+        let conditions: Vec<&Condition> = state
+            .transitions
+            .iter()
+            .flat_map(|transition| &transition.conditions)
+            .collect();
+        let commands: Vec<&str> = conditions
+            .iter()
+            .filter_map(|condition| match condition {
+                Condition::Command(command) => Some(command.as_str()),
+                _ => None,
+            })
+            .collect();
+        let needs_history = !commands.is_empty()
+            || conditions
+                .iter()
+                .any(|condition| matches!(condition, Condition::Timeout(_) | Condition::Activity));
+        let needs_timeline = needs_history
+            || conditions
+                .iter()
+                .any(|condition| matches!(condition, Condition::PullRequest(_)));
+        if needs_timeline {
+            ctx.timeline = gh.get_issue_timeline(issue.number).await?;
+        }
+        if needs_history {
+            ctx.comments = gh.get_issue_comments(issue.number).await?;
+        }
+        if !commands.is_empty() {
+            // Only look up permissions of users who typed one of the commands
+            // since the issue entered its current state.
+            let since = ctx.state_entered_at(state);
+            for comment in &ctx.comments {
+                let login = &comment.user.login;
+                if comment.created_at < since
+                    || ctx.permissions.contains_key(login)
+                    || !commands
+                        .iter()
+                        .any(|c| command::has_command(&comment.body, c))
+                {
+                    continue;
+                }
+                let permission = gh.get_collaborator_permission(login).await?;
+                ctx.permissions.insert(login.clone(), permission);
+            }
+        }
+        Ok(ctx)
+    }
+
+    pub fn permission(&self, login: &str) -> Permission {
+        self.permissions.get(login).copied().unwrap_or_default()
+    }
+
+    // has_command returns true if a user with sufficient permission typed the
+    // command in a comment since the issue entered its current state.
+    pub fn has_command(&self, state: &State, command: &str) -> bool {
+        let since = self.state_entered_at(state);
+        self.comments.iter().any(|comment| {
+            comment.created_at >= since
+                && command::has_command(&comment.body, command)
+                && self.permission(&comment.user.login) >= MIN_COMMAND_PERMISSION
+        })
+    }
+
+    // has_pull_request returns true if a pull request in the given state is linked
+    // to the issue. Pull requests are linked by closing keywords in their description,
+    // or via the development sidebar. The latter does not identify the pull request,
+    // so it is assumed to be open as long as the link exists.
+    pub fn has_pull_request(&self, state: PullRequestState) -> bool {
+        let issue_repo = references::repo_from_api_url(&self.issue.url).unwrap_or_default();
+        let mut connected = 0;
+        for event in &self.timeline {
+            match event {
+                TimelineEvent::CrossReferenced { source, .. } => {
+                    let linked = &source.issue;
+                    let Some(pull_request) = &linked.pull_request else {
+                        continue;
+                    };
+                    let linked_repo =
+                        references::repo_from_api_url(&linked.url).unwrap_or_default();
+                    let body = linked.body.as_deref().unwrap_or_default();
+                    if !references::closes_issue(body, &linked_repo, &issue_repo, self.issue.number)
+                    {
+                        continue;
+                    }
+                    let matches = match state {
+                        PullRequestState::Open => linked.state == IssueState::Open,
+                        PullRequestState::Merged => pull_request.merged_at.is_some(),
+                    };
+                    if matches {
+                        return true;
+                    }
+                }
+                TimelineEvent::Connected { .. } => connected += 1,
+                TimelineEvent::Disconnected { .. } => connected -= 1,
+                _ => {}
+            }
+        }
+        state == PullRequestState::Open && connected > 0
+    }
+
+    // label_applied_at returns when the label was most recently added to the issue,
+    // or None if it is not in the timeline.
+    pub fn label_applied_at(&self, label: &str) -> Option<DateTime<Utc>> {
+        self.timeline
+            .iter()
+            .filter_map(|event| match event {
+                TimelineEvent::Labeled {
+                    label: l,
+                    created_at,
+                    ..
+                } if l.name == label => Some(*created_at),
+                _ => None,
+            })
+            .max()
+    }
+
+    // state_entered_at returns when the issue entered the given state, i.e. when
+    // the state label was applied. Issues labeled at creation time may not have
+    // a labeled event, in which case the creation time is used.
+    pub fn state_entered_at(&self, state: &State) -> DateTime<Utc> {
+        self.label_applied_at(&state.label)
+            .unwrap_or(self.issue.created_at)
+    }
+
+    // last_activity returns the time of the most recent comment, comment edit or
+    // timeline event by anyone other than the bot itself, so that the bot's own
+    // comments and label changes are not mistaken for activity on the issue.
+    pub fn last_activity(&self) -> Option<DateTime<Utc>> {
+        let comments = self
+            .comments
+            .iter()
+            .filter(|comment| comment.user.login != self.bot_login)
+            .map(|comment| comment.created_at.max(comment.updated_at));
+        let events = self
+            .timeline
+            .iter()
+            .filter_map(|event| event.actor())
+            .filter(|(actor, _)| actor.login != self.bot_login)
+            .map(|(_, at)| at);
+        comments.chain(events).max()
+    }
+
+    // has_activity returns true if there was activity since the issue entered
+    // the given state.
+    pub fn has_activity(&self, state: &State) -> bool {
+        let since = self.state_entered_at(state);
+        self.last_activity().is_some_and(|at| at > since)
+    }
+
+    // timeout_start returns the moment from which timeout conditions of the
+    // transition are measured. If the transition also requires labels, the timeout
+    // counts from when those labels were applied, which allows chaining timeouts
+    // (e.g. add `stale` after 30 days, close if `stale` for 30 additional days).
+    // Otherwise it counts from the last activity, but not earlier than when the
+    // issue entered its current state.
+    pub fn timeout_start(&self, state: &State, transition: &Transition) -> DateTime<Utc> {
+        let state_entered = self.state_entered_at(state);
+        let labels_applied = transition
+            .conditions
+            .iter()
+            .filter_map(|condition| match condition {
+                Condition::Label(label) => {
+                    Some(self.label_applied_at(label).unwrap_or(state_entered))
+                }
+                _ => None,
+            })
+            .max();
+        match labels_applied {
+            Some(applied) => applied,
+            None => self
+                .last_activity()
+                .map_or(state_entered, |at| at.max(state_entered)),
+        }
+    }
+}
+
+// find_state returns the first state (in config order) whose label is on the issue.
+// Pull requests are matched against the pull-requests section of the config.
+pub fn find_state<'a>(config: &'a StateMachine, issue: &Issue) -> Option<&'a State> {
+    config
+        .states_for(issue)
+        .iter()
+        .find(|state| issue.has_label(&state.label))
+}
+
+// evaluate finds the current state of the issue and returns the first
+// transition of that state that applies. Returns None if the issue is
+// not in any known state or no transition applies.
+pub fn evaluate<'a>(config: &'a StateMachine, ctx: &Context) -> Option<Plan<'a>> {
+    let state = find_state(config, ctx.issue)?;
+    state
+        .transitions
+        .iter()
+        .find(|transition| transition_applies(ctx, state, transition))
+        .map(|transition| Plan { state, transition })
+}
+
+// transition_applies returns true if all conditions of the transition hold
+// and the transition has not already been applied.
+pub fn transition_applies(ctx: &Context, state: &State, transition: &Transition) -> bool {
+    transition
+        .conditions
+        .iter()
+        .all(|condition| condition_holds(ctx, state, transition, condition))
+        && !already_applied(ctx, state, transition)
+}
+
+// already_applied returns true if the transition changes labels and all of its
+// label actions would be no-ops. Conditions like timeouts keep holding after the
+// transition fires, and this prevents it from firing again on every run.
+pub fn already_applied(ctx: &Context, state: &State, transition: &Transition) -> bool {
+    let mut has_label_actions = false;
+    for action in &transition.actions {
+        let noop = match action {
+            Action::AddLabel(label) => ctx.issue.has_label(label),
+            Action::RemoveLabel(label) => !ctx.issue.has_label(label),
+            Action::ReplaceLabel(label) => &state.label == label,
+            Action::PostComment(_) | Action::Close => continue,
+        };
+        if !noop {
+            return false;
+        }
+        has_label_actions = true;
+    }
+    has_label_actions
+}
+
+pub fn condition_holds(
+    ctx: &Context,
+    state: &State,
+    transition: &Transition,
+    condition: &Condition,
+) -> bool {
+    match condition {
+        Condition::Label(label) => ctx.issue.has_label(label),
+        Condition::Activity => ctx.has_activity(state),
+        Condition::Command(command) => ctx.has_command(state, command),
+        Condition::Timeout(days) => {
+            ctx.now - ctx.timeout_start(state, transition) >= Duration::days(i64::from(*days))
+        }
+        Condition::PullRequest(state) => ctx.has_pull_request(*state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PullRequests;
+    use crate::fake_github::FakeGitHub;
+    use crate::github::{Label, PullRequestRef};
+
+    fn issue_with_labels(labels: &[&str]) -> Issue {
+        Issue {
+            number: 1,
+            labels: labels
+                .iter()
+                .map(|name| Label {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn config() -> StateMachine {
+        let yaml = r#"
+        states:
+          - description: "Issue is open"
+            label: "open"
+            transitions:
+              - description: "Issue is stale"
+                conditions:
+                  - type: "timeout"
+                    timeout: 10
+                actions:
+                  - type: "add-label"
+                    label: "stale"
+              - description: "Issue is resolved"
+                conditions:
+                  - type: "label"
+                    label: "resolved"
+                actions:
+                  - type: "remove-label"
+                    label: "open"
+                  - type: "close"
+              - description: "Issue is resolved and verified"
+                conditions:
+                  - type: "label"
+                    label: "resolved"
+                  - type: "label"
+                    label: "verified"
+                actions:
+                  - type: "close"
+          - description: "Issue is stale"
+            label: "stale"
+            transitions: []
+        "#;
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_find_state() {
+        let config = config();
+        let state = find_state(&config, &issue_with_labels(&["bug", "stale"]));
+        assert_eq!(state.unwrap().label, "stale");
+        // first matching state in config order wins
+        let state = find_state(&config, &issue_with_labels(&["stale", "open"]));
+        assert_eq!(state.unwrap().label, "open");
+        assert!(find_state(&config, &issue_with_labels(&["bug"])).is_none());
+    }
+
+    #[test]
+    fn test_find_state_pull_request() {
+        let mut config = config();
+        let mut pull_request = issue_with_labels(&["open"]);
+        pull_request.pull_request = Some(PullRequestRef::default());
+        // pull requests are not managed without the pull-requests section
+        assert!(find_state(&config, &pull_request).is_none());
+
+        config.pull_requests = Some(PullRequests {
+            states: vec![State {
+                description: "Waiting for review".into(),
+                label: "needs-review".into(),
+                transitions: vec![],
+            }],
+        });
+        assert!(find_state(&config, &pull_request).is_none());
+        pull_request.labels.push(Label {
+            name: "needs-review".into(),
+            ..Default::default()
+        });
+        assert_eq!(
+            find_state(&config, &pull_request).unwrap().label,
+            "needs-review"
+        );
+        // issues do not use pull request states
+        let issue = issue_with_labels(&["needs-review"]);
+        assert!(find_state(&config, &issue).is_none());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let config = config();
+        let evaluate_labels = |labels: &[&str]| {
+            let issue = issue_with_labels(labels);
+            let ctx = Context::new(&issue, issue.updated_at);
+            evaluate(&config, &ctx)
+        };
+
+        let plan = evaluate_labels(&["open", "resolved"]).unwrap();
+        assert_eq!(plan.state.label, "open");
+        assert_eq!(plan.transition.description, "Issue is resolved");
+        assert_eq!(plan.actions().len(), 2);
+        assert!(matches!(plan.actions()[0], Action::RemoveLabel(ref s) if s == "open"));
+        assert!(matches!(plan.actions()[1], Action::Close));
+
+        // no transition applies
+        assert!(evaluate_labels(&["open"]).is_none());
+        // state without transitions
+        assert!(evaluate_labels(&["stale", "resolved"]).is_none());
+        // issue not in any state
+        assert!(evaluate_labels(&["resolved"]).is_none());
+    }
+
+    fn day(n: i64) -> DateTime<Utc> {
+        "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::days(n)
+    }
+
+    fn user(login: &str) -> crate::github::User {
+        crate::github::User {
+            login: login.to_string(),
+        }
+    }
+
+    // labeled returns an event of the bot applying the label.
+    fn labeled(label: &str, at: DateTime<Utc>) -> TimelineEvent {
+        TimelineEvent::Labeled {
+            actor: user("bot"),
+            created_at: at,
+            label: Label {
+                name: label.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_timeout_chain() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions:
+              - description: "Mark stale"
+                conditions:
+                  - type: "timeout"
+                    timeout: 30
+                actions:
+                  - type: "add-label"
+                    label: "stale"
+                  - type: "post-comment"
+                    comment: "Marked stale."
+              - description: "Close if stale for too long"
+                conditions:
+                  - type: "label"
+                    label: "stale"
+                  - type: "timeout"
+                    timeout: 30
+                actions:
+                  - type: "post-comment"
+                    comment: "Closed."
+                  - type: "close"
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let state = &config.states[0];
+        let evaluate_at = |issue: &Issue, timeline: &[(&str, i64)], now: i64| {
+            let mut ctx = Context::new(issue, day(now));
+            ctx.bot_login = "bot".to_string();
+            ctx.timeline = timeline
+                .iter()
+                .map(|(label, at)| labeled(label, day(*at)))
+                .collect();
+            evaluate(&config, &ctx).map(|plan| plan.transition.description.clone())
+        };
+
+        let mut issue = issue_with_labels(&["needs-info"]);
+        issue.created_at = day(-10);
+        issue.updated_at = day(0);
+        let timeline = [("bug", -10), ("needs-info", 0)];
+        assert_eq!(evaluate_at(&issue, &timeline, 29), None);
+        assert_eq!(
+            evaluate_at(&issue, &timeline, 30).as_deref(),
+            Some("Mark stale")
+        );
+
+        // the bot marked the issue stale on day 30
+        issue.labels.push(Label {
+            name: "stale".into(),
+            ..Default::default()
+        });
+        issue.updated_at = day(30);
+        let timeline = [("bug", -10), ("needs-info", 0), ("stale", 30)];
+        assert_eq!(evaluate_at(&issue, &timeline, 45), None);
+        // "Mark stale" is already applied, the stale timeout counts from day 30
+        assert_eq!(
+            evaluate_at(&issue, &timeline, 60).as_deref(),
+            Some("Close if stale for too long")
+        );
+
+        let mut ctx = Context::new(&issue, day(60));
+        ctx.bot_login = "bot".to_string();
+        assert_eq!(ctx.state_entered_at(state), day(-10));
+        ctx.timeline = timeline
+            .iter()
+            .map(|(label, at)| labeled(label, day(*at)))
+            .collect();
+        ctx.timeline.push(labeled("needs-info", day(20)));
+        assert_eq!(ctx.state_entered_at(state), day(20));
+        assert_eq!(ctx.label_applied_at("stale"), Some(day(30)));
+        assert_eq!(ctx.label_applied_at("help-wanted"), None);
+        // the bot's own label changes are not activity
+        assert_eq!(ctx.timeout_start(state, &state.transitions[0]), day(20));
+        assert_eq!(ctx.timeout_start(state, &state.transitions[1]), day(30));
+        assert!(already_applied(&ctx, state, &state.transitions[0]));
+        assert!(!already_applied(&ctx, state, &state.transitions[1]));
+    }
+
+    #[test]
+    fn test_command() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for triage"
+            label: "needs-triage"
+            transitions:
+              - description: "Request more info"
+                conditions:
+                  - type: "command"
+                    command: "needs-info"
+                actions:
+                  - type: "replace-label"
+                    label: "needs-info"
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let issue = issue_with_labels(&["needs-triage"]);
+        let comment = |login: &str, body: &str, at: i64| Comment {
+            user: user(login),
+            body: body.to_string(),
+            created_at: day(at),
+            ..Default::default()
+        };
+        let mut ctx = Context::new(&issue, day(10));
+        ctx.timeline = vec![labeled("needs-triage", day(1))];
+        ctx.permissions
+            .insert("maintainer".to_string(), Permission::Triage);
+        ctx.permissions
+            .insert("reporter".to_string(), Permission::Read);
+        ctx.comments = vec![
+            // typed before the issue entered the current state
+            comment("maintainer", "needs-info", 0),
+            // typed by a user without triage permission
+            comment("reporter", "needs-info", 2),
+            // typed by a user with unknown permission
+            comment("someone", "/needs-info", 2),
+        ];
+        assert!(evaluate(&config, &ctx).is_none());
+
+        ctx.comments
+            .push(comment("maintainer", "Please add logs.\n/needs-info", 3));
+        let plan = evaluate(&config, &ctx).unwrap();
+        assert_eq!(plan.transition.description, "Request more info");
+    }
+
+    #[test]
+    fn test_activity() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions:
+              - description: "Go back to triage on activity"
+                conditions:
+                  - type: "activity"
+                actions:
+                  - type: "replace-label"
+                    label: "needs-triage"
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let state = &config.states[0];
+        let issue = issue_with_labels(&["needs-info"]);
+        let comment = |login: &str, created: i64, updated: i64| Comment {
+            user: user(login),
+            created_at: day(created),
+            updated_at: day(updated),
+            ..Default::default()
+        };
+        let mut ctx = Context::new(&issue, day(10));
+        ctx.bot_login = "bot".to_string();
+        ctx.timeline = vec![labeled("needs-info", day(2))];
+        ctx.comments = vec![
+            // the maintainer's command that moved the issue into needs-info
+            comment("maintainer", 1, 1),
+            // the bot's own comment
+            comment("bot", 2, 2),
+        ];
+        assert_eq!(ctx.last_activity(), Some(day(1)));
+        assert!(!ctx.has_activity(state));
+        assert!(evaluate(&config, &ctx).is_none());
+
+        // the bot's own label changes are not activity
+        ctx.timeline.push(labeled("stale", day(5)));
+        assert!(!ctx.has_activity(state));
+
+        // editing an earlier comment is activity
+        ctx.comments.push(comment("reporter", 0, 4));
+        assert!(ctx.has_activity(state));
+        assert_eq!(ctx.last_activity(), Some(day(4)));
+
+        // and so are events by other users
+        ctx.comments.pop();
+        ctx.timeline.push(TimelineEvent::Renamed {
+            actor: user("reporter"),
+            created_at: day(6),
+        });
+        assert_eq!(ctx.last_activity(), Some(day(6)));
+        let plan = evaluate(&config, &ctx).unwrap();
+        assert_eq!(plan.transition.description, "Go back to triage on activity");
+    }
+
+    fn cross_referenced(number: u32, state: IssueState, merged: bool, body: &str) -> TimelineEvent {
+        TimelineEvent::CrossReferenced {
+            actor: user("contributor"),
+            created_at: day(1),
+            source: crate::github::CrossReferenceSource {
+                issue: crate::github::LinkedIssue {
+                    number,
+                    url: format!(
+                        "https://api.github.com/repos/yurishkuro/rustybot/issues/{}",
+                        number
+                    ),
+                    state,
+                    body: Some(body.to_string()),
+                    pull_request: Some(PullRequestRef {
+                        merged_at: merged.then(|| day(2)),
+                        ..Default::default()
+                    }),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_pull_request() {
+        let mut issue = issue_with_labels(&["help-wanted"]);
+        issue.number = 12;
+        issue.url = "https://api.github.com/repos/yurishkuro/rustybot/issues/12".into();
+        let mut ctx = Context::new(&issue, day(10));
+        assert!(!ctx.has_pull_request(PullRequestState::Open));
+
+        // mentions without closing keywords do not count
+        ctx.timeline = vec![cross_referenced(
+            20,
+            IssueState::Open,
+            false,
+            "Related to #12",
+        )];
+        assert!(!ctx.has_pull_request(PullRequestState::Open));
+
+        ctx.timeline
+            .push(cross_referenced(21, IssueState::Open, false, "Fixes #12"));
+        assert!(ctx.has_pull_request(PullRequestState::Open));
+        assert!(!ctx.has_pull_request(PullRequestState::Merged));
+
+        ctx.timeline = vec![cross_referenced(21, IssueState::Closed, true, "Fixes #12")];
+        assert!(!ctx.has_pull_request(PullRequestState::Open));
+        assert!(ctx.has_pull_request(PullRequestState::Merged));
+
+        // linked via the development sidebar
+        let connected = |connect: bool| {
+            let (actor, created_at) = (user("contributor"), day(3));
+            if connect {
+                TimelineEvent::Connected { actor, created_at }
+            } else {
+                TimelineEvent::Disconnected { actor, created_at }
+            }
+        };
+        ctx.timeline = vec![connected(true)];
+        assert!(ctx.has_pull_request(PullRequestState::Open));
+        ctx.timeline.push(connected(false));
+        assert!(!ctx.has_pull_request(PullRequestState::Open));
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for triage"
+            label: "needs-triage"
+            transitions:
+              - description: "Request more info"
+                conditions:
+                  - type: "command"
+                    command: "needs-info"
+                actions:
+                  - type: "replace-label"
+                    label: "needs-info"
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions:
+              - description: "Resolved"
+                conditions:
+                  - type: "label"
+                    label: "resolved"
+                actions:
+                  - type: "close"
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let comment = |login: &str, body: &str, at: i64| Comment {
+            user: user(login),
+            body: body.to_string(),
+            created_at: day(at),
+            ..Default::default()
+        };
+        let issue = issue_with_labels(&["needs-triage"]);
+        let mut gh = FakeGitHub::new(vec![issue.clone()]);
+        gh.timelines
+            .insert(1, vec![labeled("needs-triage", day(1))]);
+        gh.comments.insert(
+            1,
+            vec![
+                comment("maintainer", "needs-info", 0),
+                comment("reporter", "needs-info", 2),
+                comment("reporter", "thanks", 3),
+                comment("maintainer", "/needs-info", 3),
+            ],
+        );
+        gh.permissions
+            .insert("maintainer".to_string(), Permission::Triage);
+
+        let ctx = Context::load(&gh, &config, &issue, day(10), "bot")
+            .await
+            .unwrap();
+        assert_eq!(ctx.timeline.len(), 1);
+        assert_eq!(ctx.comments.len(), 4);
+        // permissions are only looked up for the authors of commands typed
+        // since the issue entered the state, once per author
+        assert_eq!(gh.reads("get_collaborator_permission"), 2);
+        assert_eq!(ctx.permission("maintainer"), Permission::Triage);
+        assert_eq!(ctx.permission("reporter"), Permission::None);
+        let plan = evaluate(&config, &ctx).unwrap();
+        assert_eq!(plan.transition.description, "Request more info");
+
+        // label conditions do not need the timeline or comments
+        let issue = issue_with_labels(&["needs-info"]);
+        let gh = FakeGitHub::new(vec![issue.clone()]);
+        Context::load(&gh, &config, &issue, day(10), "bot")
+            .await
+            .unwrap();
+        assert_eq!(gh.reads("get_issue_timeline"), 0);
+        assert_eq!(gh.reads("get_issue_comments"), 0);
+    }
+}
@@ -0,0 +1,152 @@
+use serde::Deserialize;
+
+// Events that affect a single issue or pull request. When the bot runs as
+// an action triggered by one of them, only that issue is evaluated.
+const ISSUE_EVENTS: &[&str] = &[
+    "issues",
+    "issue_comment",
+    "pull_request",
+    "pull_request_target",
+    "pull_request_review",
+    "pull_request_review_comment",
+];
+
+#[derive(Deserialize, Debug)]
+struct NumberRef {
+    number: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct RepositoryRef {
+    full_name: String,
+}
+
+// Payload is the subset of the webhook payload used to find the affected issue.
+// See https://docs.github.com/en/webhooks/webhook-events-and-payloads
+#[derive(Deserialize, Debug)]
+struct Payload {
+    issue: Option<NumberRef>,
+    pull_request: Option<NumberRef>,
+    repository: Option<RepositoryRef>,
+}
+
+// Target is the issue or pull request affected by an event.
+#[derive(Debug, PartialEq)]
+pub struct Target {
+    // repository in the owner/name form
+    pub repo: Option<String>,
+    pub issue_number: u32,
+}
+
+// parse returns the issue or pull request affected by the event, or None
+// if the event is not about a single issue (e.g. schedule or workflow_dispatch).
+pub fn parse(
+    event_name: &str,
+    payload: &str,
+) -> Result<Option<Target>, Box<dyn std::error::Error>> {
+    if !ISSUE_EVENTS.contains(&event_name) {
+        return Ok(None);
+    }
+    let payload: Payload = serde_json::from_str(payload)?;
+    let issue_number = payload
+        .issue
+        .or(payload.pull_request)
+        .map(|issue| issue.number)
+        .ok_or_else(|| format!("{} event payload has no issue or pull_request", event_name))?;
+    Ok(Some(Target {
+        repo: payload.repository.map(|repo| repo.full_name),
+        issue_number,
+    }))
+}
+
+// issue_number returns the number of the issue or pull request affected by the event,
+// or None if the event is not about a single issue.
+pub fn issue_number(
+    event_name: &str,
+    payload: &str,
+) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+    Ok(parse(event_name, payload)?.map(|target| target.issue_number))
+}
+
+// read_issue_number reads the event payload from the file and returns
+// the number of the affected issue, see issue_number.
+pub fn read_issue_number(
+    event_name: &str,
+    event_path: &str,
+) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+    if !ISSUE_EVENTS.contains(&event_name) {
+        return Ok(None);
+    }
+    let payload = std::fs::read_to_string(event_path)
+        .map_err(|err| format!("cannot read event payload {}: {}", event_path, err))?;
+    issue_number(event_name, &payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_issue_number() {
+        let payload = r#"
+        {
+            "action": "created",
+            "issue": {
+                "number": 12,
+                "title": "Issue 12",
+                "labels": [{ "name": "help-wanted" }]
+            },
+            "comment": {
+                "id": 1001,
+                "body": "/in-progress",
+                "user": { "login": "octocat" }
+            },
+            "repository": { "full_name": "yurishkuro/rustybot" }
+        }
+        "#;
+        assert_eq!(issue_number("issue_comment", payload).unwrap(), Some(12));
+        assert_eq!(
+            parse("issue_comment", payload).unwrap(),
+            Some(Target {
+                repo: Some("yurishkuro/rustybot".to_string()),
+                issue_number: 12
+            })
+        );
+        assert_eq!(issue_number("issues", payload).unwrap(), Some(12));
+
+        let payload = r#"
+        {
+            "action": "opened",
+            "number": 13,
+            "pull_request": { "number": 13, "title": "Fixes #12" }
+        }
+        "#;
+        assert_eq!(issue_number("pull_request", payload).unwrap(), Some(13));
+
+        // events that are not about a single issue
+        assert_eq!(issue_number("schedule", "{}").unwrap(), None);
+        assert_eq!(issue_number("workflow_dispatch", "").unwrap(), None);
+
+        let err = issue_number("issues", r#"{"action": "opened"}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "issues event payload has no issue or pull_request"
+        );
+    }
+
+    #[test]
+    fn test_read_issue_number() {
+        let mut file = NamedTempFile::new().expect("Failed to create temporary file");
+        file.write_all(br#"{"action": "labeled", "issue": {"number": 7}}"#)
+            .unwrap();
+        file.flush().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        assert_eq!(read_issue_number("issues", path).unwrap(), Some(7));
+        // the payload is not read for other events
+        assert_eq!(read_issue_number("schedule", "/nonexistent").unwrap(), None);
+        assert!(read_issue_number("issues", "/nonexistent").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use super::config::{Action, StateMachine};
+use super::github::{GitHub, Issue};
+use super::github_error::GitHubError;
+
+// ActionResult records the outcome of applying a single action.
+#[derive(Debug)]
+pub struct ActionResult<'a> {
+    pub action: &'a Action,
+    pub result: Result<(), GitHubError>,
+}
+
+// execute applies the actions to the issue in order, stopping at the first
+// failure. It returns one result per attempted action, so the last result
+// is the failed one if any action failed.
+//
+// The executor tracks the issue labels locally as it goes, so that later
+// actions see the effect of earlier ones (e.g. replace-label followed by
+// remove-label) and label actions that would not change anything are not
+// sent to GitHub.
+pub async fn execute<'a>(
+    gh: &dyn GitHub,
+    config: &StateMachine,
+    issue: &Issue,
+    actions: &'a [Action],
+) -> Vec<ActionResult<'a>> {
+    let mut labels: Vec<String> = issue.labels.iter().map(|l| l.name.clone()).collect();
+    let mut results = Vec::new();
+    for action in actions {
+        let result = apply(gh, config, issue, &mut labels, action).await;
+        let failed = result.is_err();
+        results.push(ActionResult { action, result });
+        if failed {
+            break;
+        }
+    }
+    results
+}
+
+async fn apply(
+    gh: &dyn GitHub,
+    config: &StateMachine,
+    issue: &Issue,
+    labels: &mut Vec<String>,
+    action: &Action,
+) -> Result<(), GitHubError> {
+    let issue_number = issue.number;
+    match action {
+        Action::AddLabel(label) => {
+            if labels.contains(label) {
+                return Ok(());
+            }
+            gh.add_labels(issue_number, std::slice::from_ref(label))
+                .await?;
+            labels.push(label.clone());
+        }
+        Action::RemoveLabel(label) => {
+            if !labels.contains(label) {
+                return Ok(());
+            }
+            gh.remove_label(issue_number, label).await?;
+            labels.retain(|l| l != label);
+        }
+        Action::ReplaceLabel(label) => {
+            // Drop all state labels and add the new one in a single request,
+            // so the issue is never observed without a state or in two states.
+            let states = config.states_for(issue);
+            let mut new_labels: Vec<String> = labels
+                .iter()
+                .filter(|l| *l != label && !states.iter().any(|s| &s.label == *l))
+                .cloned()
+                .collect();
+            new_labels.push(label.clone());
+            if new_labels.len() == labels.len() && new_labels.iter().all(|l| labels.contains(l)) {
+                return Ok(());
+            }
+            gh.set_labels(issue_number, &new_labels).await?;
+            *labels = new_labels;
+        }
+        Action::PostComment(comment) => gh.post_comment(issue_number, comment).await?,
+        Action::Close => gh.close_issue(issue_number).await?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Auth;
+    use crate::github::Label;
+    use crate::github_client::{self, Client};
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config() -> StateMachine {
+        let yaml = r#"
+        states:
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions: []
+          - description: "Waiting for triage"
+            label: "needs-triage"
+            transitions: []
+        "#;
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn issue() -> Issue {
+        Issue {
+            number: 7,
+            labels: ["bug", "needs-info", "stale"]
+                .iter()
+                .map(|name| Label {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn client(mock_server: &MockServer) -> Client {
+        Client {
+            api_url: mock_server.uri(),
+            auth: Auth::Token(String::new()),
+            repo_owner: String::from("yurishkuro"),
+            repo_name: String::from("rustybot"),
+            per_page: github_client::DEFAULT_PER_PAGE,
+            retry: Default::default(),
+            rate_limit: Default::default(),
+            cache: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/yurishkuro/rustybot/issues/7/labels"))
+            .and(body_json(serde_json::json!({
+                "labels": ["bug", "stale", "needs-triage"]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/repos/yurishkuro/rustybot/issues/7/labels/stale"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/yurishkuro/rustybot/issues/7/comments"))
+            .and(body_json(serde_json::json!({ "body": "Back to triage." })))
+            .respond_with(ResponseTemplate::new(201).set_body_string("{}"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let actions = vec![
+            Action::ReplaceLabel("needs-triage".into()),
+            Action::RemoveLabel("stale".into()),
+            // no-op since the label was already removed
+            Action::RemoveLabel("stale".into()),
+            // no-op since the label is already present
+            Action::AddLabel("bug".into()),
+            Action::PostComment("Back to triage.".into()),
+        ];
+        let results = execute(&client(&mock_server), &config(), &issue(), &actions).await;
+        assert_eq!(results.len(), 5);
+        for result in results {
+            assert!(result.result.is_ok(), "{:?}", result);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_stops_on_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/yurishkuro/rustybot/issues/7/labels"))
+            .respond_with(ResponseTemplate::new(403).set_body_string("Forbidden"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let actions = vec![Action::AddLabel("wontfix".into()), Action::Close];
+        let results = execute(&client(&mock_server), &config(), &issue(), &actions).await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0].action, Action::AddLabel(_)));
+        assert_eq!(
+            results[0].result.as_ref().unwrap_err().to_string(),
+            "forbidden: Status: 403 - Forbidden"
+        );
+    }
+}
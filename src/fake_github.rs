@@ -0,0 +1,193 @@
+use super::github::{
+    Comment, GitHub, Issue, IssueState, Label, Permission, RepoFile, TimelineEvent,
+};
+use super::github_error::GitHubError;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// FakeGitHub is an in-memory GitHub for tests. It serves the issues, timelines,
+// comments and permissions it was given, applies mutations to the issues so
+// later reads see them, and records every mutation in order.
+#[derive(Default)]
+pub struct FakeGitHub {
+    pub issues: Mutex<Vec<Issue>>,
+    pub timelines: HashMap<u32, Vec<TimelineEvent>>,
+    pub comments: HashMap<u32, Vec<Comment>>,
+    pub permissions: HashMap<String, Permission>,
+    // files of the default branch, by path
+    pub files: HashMap<String, RepoFile>,
+    // mutations applied so far
+    pub mutations: Mutex<Vec<Mutation>>,
+    // mutations that fail once with a server error instead of being applied
+    pub failures: Mutex<Vec<Mutation>>,
+    // number of read requests, by method name
+    pub reads: Mutex<HashMap<&'static str, usize>>,
+}
+
+// Mutation is a write request received by FakeGitHub.
+#[derive(Debug, PartialEq)]
+pub enum Mutation {
+    AddLabels(u32, Vec<String>),
+    RemoveLabel(u32, String),
+    SetLabels(u32, Vec<String>),
+    PostComment(u32, String),
+    CloseIssue(u32),
+}
+
+impl FakeGitHub {
+    pub fn new(issues: Vec<Issue>) -> Self {
+        FakeGitHub {
+            issues: Mutex::new(issues),
+            ..Default::default()
+        }
+    }
+
+    // take_mutations returns the mutations recorded since the last call.
+    pub fn take_mutations(&self) -> Vec<Mutation> {
+        std::mem::take(&mut *self.mutations.lock().unwrap())
+    }
+
+    pub fn reads(&self, method: &str) -> usize {
+        self.reads.lock().unwrap().get(method).copied().unwrap_or(0)
+    }
+
+    fn read(&self, method: &'static str) {
+        *self.reads.lock().unwrap().entry(method).or_default() += 1;
+    }
+
+    // mutate records the mutation and applies `update` to the issue.
+    fn mutate(
+        &self,
+        issue_number: u32,
+        mutation: Mutation,
+        update: impl FnOnce(&mut Issue),
+    ) -> Result<(), GitHubError> {
+        let mut failures = self.failures.lock().unwrap();
+        if let Some(i) = failures.iter().position(|failure| *failure == mutation) {
+            failures.remove(i);
+            return Err(GitHubError::Status {
+                status: 502,
+                message: "Bad Gateway".to_string(),
+            });
+        }
+        let mut issues = self.issues.lock().unwrap();
+        let issue = issues
+            .iter_mut()
+            .find(|issue| issue.number == issue_number)
+            .ok_or_else(|| not_found(issue_number))?;
+        update(issue);
+        self.mutations.lock().unwrap().push(mutation);
+        Ok(())
+    }
+}
+
+fn not_found(issue_number: u32) -> GitHubError {
+    GitHubError::NotFound {
+        status: 404,
+        message: format!("issue #{} not found", issue_number),
+    }
+}
+
+fn label(name: &str) -> Label {
+    Label {
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl GitHub for FakeGitHub {
+    async fn get_open_issues(
+        &self,
+        include_pull_requests: bool,
+    ) -> Result<Vec<Issue>, GitHubError> {
+        self.read("get_open_issues");
+        Ok(self
+            .issues
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|issue| issue.state == IssueState::Open)
+            .filter(|issue| include_pull_requests || !issue.is_pull_request())
+            .cloned()
+            .collect())
+    }
+
+    async fn get_issue(&self, issue_number: u32) -> Result<Issue, GitHubError> {
+        self.read("get_issue");
+        self.issues
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|issue| issue.number == issue_number)
+            .cloned()
+            .ok_or_else(|| not_found(issue_number))
+    }
+
+    async fn get_issue_timeline(
+        &self,
+        issue_number: u32,
+    ) -> Result<Vec<TimelineEvent>, GitHubError> {
+        self.read("get_issue_timeline");
+        Ok(self
+            .timelines
+            .get(&issue_number)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_issue_comments(&self, issue_number: u32) -> Result<Vec<Comment>, GitHubError> {
+        self.read("get_issue_comments");
+        Ok(self
+            .comments
+            .get(&issue_number)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_collaborator_permission(&self, login: &str) -> Result<Permission, GitHubError> {
+        self.read("get_collaborator_permission");
+        Ok(self.permissions.get(login).copied().unwrap_or_default())
+    }
+
+    async fn add_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError> {
+        let mutation = Mutation::AddLabels(issue_number, labels.to_vec());
+        self.mutate(issue_number, mutation, |issue| {
+            for name in labels {
+                if !issue.has_label(name) {
+                    issue.labels.push(label(name));
+                }
+            }
+        })
+    }
+
+    async fn remove_label(&self, issue_number: u32, name: &str) -> Result<(), GitHubError> {
+        let mutation = Mutation::RemoveLabel(issue_number, name.to_string());
+        self.mutate(issue_number, mutation, |issue| {
+            issue.labels.retain(|l| l.name != name)
+        })
+    }
+
+    async fn set_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError> {
+        let mutation = Mutation::SetLabels(issue_number, labels.to_vec());
+        self.mutate(issue_number, mutation, |issue| {
+            issue.labels = labels.iter().map(|name| label(name)).collect()
+        })
+    }
+
+    async fn post_comment(&self, issue_number: u32, body: &str) -> Result<(), GitHubError> {
+        let mutation = Mutation::PostComment(issue_number, body.to_string());
+        self.mutate(issue_number, mutation, |issue| issue.comments += 1)
+    }
+
+    async fn close_issue(&self, issue_number: u32) -> Result<(), GitHubError> {
+        self.mutate(issue_number, Mutation::CloseIssue(issue_number), |issue| {
+            issue.state = IssueState::Closed
+        })
+    }
+
+    async fn get_file(&self, path: &str) -> Result<Option<RepoFile>, GitHubError> {
+        self.read("get_file");
+        Ok(self.files.get(path).cloned())
+    }
+}
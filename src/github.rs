@@ -1,15 +1,99 @@
-use serde::Deserialize;
+use super::github_error::GitHubError;
+use super::rate_limit::RateLimit;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
 
-pub trait GitHub {
-    async fn get_open_issues(&self) -> Result<Vec<Issue>, Box<dyn std::error::Error>>;
+// GitHub is the API used by the engine and the executor. It is object-safe,
+// so the backend can be chosen at runtime and replaced by a fake in tests.
+#[async_trait::async_trait]
+pub trait GitHub: Send + Sync {
+    // get_open_issues lists open issues of the repository. The GitHub API returns
+    // pull requests as issues too; they are only included if requested.
+    async fn get_open_issues(&self, include_pull_requests: bool)
+        -> Result<Vec<Issue>, GitHubError>;
+
+    async fn get_issue(&self, issue_number: u32) -> Result<Issue, GitHubError>;
+
+    // get_issue_timeline returns the events of the issue in chronological order.
+    async fn get_issue_timeline(
+        &self,
+        issue_number: u32,
+    ) -> Result<Vec<TimelineEvent>, GitHubError>;
+
+    async fn get_issue_comments(&self, issue_number: u32) -> Result<Vec<Comment>, GitHubError>;
+
+    // get_collaborator_permission returns the permission level of the user
+    // in the repository.
+    async fn get_collaborator_permission(&self, login: &str) -> Result<Permission, GitHubError>;
+
+    async fn add_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError>;
+
+    async fn remove_label(&self, issue_number: u32, label: &str) -> Result<(), GitHubError>;
+
+    // set_labels replaces all labels of the issue in a single request.
+    async fn set_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError>;
+
+    async fn post_comment(&self, issue_number: u32, body: &str) -> Result<(), GitHubError>;
+
+    async fn close_issue(&self, issue_number: u32) -> Result<(), GitHubError>;
+
+    // get_file returns a file from the default branch of the repository,
+    // or None if it does not exist.
+    async fn get_file(&self, path: &str) -> Result<Option<RepoFile>, GitHubError>;
+
+    // rate_limit returns the API budget reported by the last response, if known.
+    fn rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, Clone)]
 pub struct User {
     pub login: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(dead_code)]
+pub struct Label {
+    pub name: String,
+    #[serde(default)]
+    pub color: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueState {
+    #[default]
+    Open,
+    Closed,
+}
+
+// AuthorAssociation is the relationship of the issue author to the repository.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuthorAssociation {
+    Collaborator,
+    Contributor,
+    FirstTimer,
+    FirstTimeContributor,
+    Mannequin,
+    Member,
+    #[default]
+    None,
+    Owner,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(dead_code)]
+pub struct Milestone {
+    pub number: u32,
+    pub title: String,
+    pub state: IssueState,
+    pub due_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
 #[allow(dead_code)]
 pub struct Issue {
     pub number: u32,
@@ -17,4 +101,167 @@ pub struct Issue {
     pub body: Option<String>,
     pub url: String,
     pub user: User,
+    pub labels: Vec<Label>,
+    pub state: IssueState,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub assignees: Vec<User>,
+    pub milestone: Option<Milestone>,
+    // comments is the number of comments on the issue.
+    pub comments: u32,
+    pub author_association: AuthorAssociation,
+    // pull_request is only present if the issue is a pull request.
+    #[serde(default)]
+    pub pull_request: Option<PullRequestRef>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(dead_code)]
+pub struct PullRequestRef {
+    pub url: String,
+    #[serde(default)]
+    pub merged_at: Option<DateTime<Utc>>,
+}
+
+// LinkedIssue is the issue or pull request that cross-referenced an issue.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(dead_code)]
+pub struct LinkedIssue {
+    pub number: u32,
+    pub url: String,
+    pub state: IssueState,
+    pub body: Option<String>,
+    #[serde(default)]
+    pub pull_request: Option<PullRequestRef>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct CrossReferenceSource {
+    pub issue: LinkedIssue,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(dead_code)]
+pub struct Comment {
+    pub id: u64,
+    pub user: User,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub author_association: AuthorAssociation,
+}
+
+// RepoFile is a file in the repository, identified by the SHA of its blob,
+// which changes whenever the content does.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RepoFile {
+    pub path: String,
+    pub sha: String,
+    pub content: String,
+}
+
+// Permission is the role of a user in the repository, ordered by access level.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    #[default]
+    None,
+    Read,
+    Triage,
+    Write,
+    Maintain,
+    Admin,
+}
+
+// TimelineEvent is an entry in the issue timeline. Only the events used by
+// the state machine are modeled, the rest are deserialized as Other.
+#[derive(Deserialize, Debug, Clone)]
+#[allow(dead_code)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum TimelineEvent {
+    Labeled {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+        label: Label,
+    },
+    Unlabeled {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+        label: Label,
+    },
+    // Renamed is recorded when the issue title is edited.
+    Renamed {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+    },
+    Reopened {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+    },
+    // CrossReferenced is recorded when another issue or pull request mentions the issue.
+    #[serde(rename = "cross-referenced")]
+    CrossReferenced {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+        source: CrossReferenceSource,
+    },
+    // Connected and Disconnected are recorded when a pull request is linked to or
+    // unlinked from the issue via the development sidebar. The REST API does not
+    // say which pull request it was.
+    Connected {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+    },
+    Disconnected {
+        #[serde(deserialize_with = "ghost")]
+        actor: User,
+        created_at: DateTime<Utc>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+// ghost deserializes the actor of an event, which is null if the account was
+// deleted, as a user without login, like the GraphQL backend does.
+fn ghost<'de, D: Deserializer<'de>>(deserializer: D) -> Result<User, D::Error> {
+    Ok(Option::<User>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl TimelineEvent {
+    // actor returns the user who caused the event and when it happened.
+    pub fn actor(&self) -> Option<(&User, DateTime<Utc>)> {
+        match self {
+            TimelineEvent::Labeled {
+                actor, created_at, ..
+            }
+            | TimelineEvent::Unlabeled {
+                actor, created_at, ..
+            }
+            | TimelineEvent::CrossReferenced {
+                actor, created_at, ..
+            }
+            | TimelineEvent::Renamed { actor, created_at }
+            | TimelineEvent::Reopened { actor, created_at }
+            | TimelineEvent::Connected { actor, created_at }
+            | TimelineEvent::Disconnected { actor, created_at } => Some((actor, *created_at)),
+            TimelineEvent::Other => None,
+        }
+    }
+}
+
+impl Issue {
+    pub fn is_pull_request(&self) -> bool {
+        self.pull_request.is_some()
+    }
+
+    pub fn has_label(&self, name: &str) -> bool {
+        self.labels.iter().any(|label| label.name == name)
+    }
 }
@@ -1,59 +1,412 @@
+use super::auth::Auth;
 use super::github::GitHub;
-use super::github::Issue;
+use super::github::{Comment, Issue, Permission, RepoFile, TimelineEvent};
+use super::github_error::{GitHubError, GraphQLError};
+use super::rate_limit::{is_idempotent, RateLimit, RateLimiter, RetryPolicy};
+use super::response_cache::ResponseCache;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::sync::Arc;
+
+// DEFAULT_PER_PAGE is the maximum page size supported by GitHub list endpoints.
+pub const DEFAULT_PER_PAGE: u32 = 100;
 
 pub struct Client {
     pub api_url: String,
-    pub token: String,
+    pub auth: Auth,
     pub repo_owner: String,
     pub repo_name: String,
+    pub per_page: u32,
+    pub retry: RetryPolicy,
+    // shared by the clients using the same token, see RateLimiters
+    pub rate_limit: Arc<RateLimiter>,
+    // responses of GET requests, revalidated with conditional requests
+    pub cache: Option<ResponseCache>,
 }
 
-impl GitHub for Client {
-    async fn get_open_issues(&self) -> Result<Vec<Issue>, Box<dyn std::error::Error>> {
-        let url = format!(
-            "{}/repos/{}/{}/issues?state=open",
+impl Client {
+    fn issues_url(&self) -> String {
+        format!(
+            "{}/repos/{}/{}/issues",
             self.api_url, self.repo_owner, self.repo_name,
-        );
+        )
+    }
+
+    fn request(&self, method: reqwest::Method, url: reqwest::Url) -> reqwest::RequestBuilder {
         let client = reqwest::Client::new();
-        let mut req = client.get(url).header("User-Agent", "rust/reqwest");
-        if !self.token.is_empty() {
-            req = req.header("Authorization", format!("Bearer {}", self.token));
+        client
+            .request(method, url)
+            .header("User-Agent", "rust/reqwest")
+            .header("Accept", "application/vnd.github+json")
+    }
+
+    // send executes the request and returns the response body,
+    // or GitHubError if the response status is not successful.
+    async fn send(&self, req: reqwest::RequestBuilder) -> Result<String, GitHubError> {
+        let (_, _, body) = self.send_with_headers(req, false).await?;
+        Ok(body)
+    }
+
+    // send_with_headers is like send, but also returns the response status and headers.
+    // 304 Not Modified is returned as a response, for conditional requests. Transient
+    // errors are only retried for idempotent methods, or if read_only is set for a
+    // request that changes nothing whatever its method, e.g. a GraphQL query.
+    async fn send_with_headers(
+        &self,
+        req: reqwest::RequestBuilder,
+        read_only: bool,
+    ) -> Result<(StatusCode, HeaderMap, String), GitHubError> {
+        // the token is added when the request is sent, since app installation tokens
+        // may need to be requested or refreshed first
+        let token = self
+            .auth
+            .token(&self.api_url, &self.repo_owner, &self.repo_name)
+            .await?;
+        let req = match token {
+            Some(token) => req.header("Authorization", format!("Bearer {}", token)),
+            None => req,
+        };
+        let (client, req) = req.build_split();
+        let req = req?;
+        let idempotent = read_only || is_idempotent(req.method());
+        let mut attempt = 0;
+        loop {
+            self.rate_limit.wait(&self.retry).await?;
+            let req = req
+                .try_clone()
+                .ok_or_else(|| GitHubError::Request("request cannot be retried".to_string()))?;
+            let res = match client.execute(req).await {
+                Ok(res) => res,
+                // the request may have been applied before the connection failed
+                Err(err)
+                    if (err.is_connect() || err.is_timeout())
+                        && idempotent
+                        && attempt < self.retry.max_retries =>
+                {
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(err) => return Err(GitHubError::Network(err)),
+            };
+            let status = res.status();
+            let headers = res.headers().clone();
+            self.rate_limit.update(&headers);
+            let body = res.text().await?;
+            if status.is_success() || status == StatusCode::NOT_MODIFIED {
+                return Ok((status, headers, body));
+            }
+            let delay = self
+                .retry
+                .retry_delay(status, &headers, &body, attempt, idempotent);
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            let exhausted_until = RateLimit::from_headers(&headers)
+                .filter(|limit| limit.remaining == 0)
+                .map(|limit| limit.reset);
+            return Err(GitHubError::from_response(
+                status.as_u16(),
+                &body,
+                exhausted_until,
+            ));
+        }
+    }
+
+    // get sends a GET request and returns the response headers and body. With a cache,
+    // the request is conditional on the cached response, which is returned if the
+    // resource has not changed.
+    async fn get(&self, url: reqwest::Url) -> Result<(HeaderMap, String), GitHubError> {
+        let Some(cache) = &self.cache else {
+            let req = self.request(reqwest::Method::GET, url);
+            let (_, headers, body) = self.send_with_headers(req, true).await?;
+            return Ok((headers, body));
+        };
+        let cached = cache.get(url.as_str());
+        let mut req = self.request(reqwest::Method::GET, url.clone());
+        if let Some(entry) = &cached {
+            req = req.headers(entry.conditional_headers());
         }
-        let res = req.send().await?;
-        let status = res.status().as_u16();
-        let body = res.text().await?;
-        // println!("Status: {}", status);
-        // println!("Response body: {}", body);
-        if status != 200 {
-            return Err(Box::new(GitHubError {
-                code: status,
-                message: body,
-            }));
+        let (status, headers, body) = self.send_with_headers(req, true).await?;
+        match cached {
+            Some(entry) if status == StatusCode::NOT_MODIFIED => Ok((entry.headers(), entry.body)),
+            _ => {
+                // a failure to cache only costs a full response next time
+                if let Err(err) = cache.put(url.as_str(), &headers, &body) {
+                    eprintln!("warning: cannot cache response of {}: {}", url, err);
+                }
+                Ok((headers, body))
+            }
         }
+    }
+
+    // get_all_pages fetches a list endpoint following the `Link: <...>; rel="next"`
+    // response headers and returns the items from all pages.
+    async fn get_all_pages<T: serde::de::DeserializeOwned>(
+        &self,
+        mut url: reqwest::Url,
+    ) -> Result<Vec<T>, GitHubError> {
+        url.query_pairs_mut()
+            .append_pair("per_page", &self.per_page.to_string());
+        let mut items = Vec::new();
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let (headers, body) = self.get(url).await?;
+            let page: Vec<T> = serde_json::from_str(&body)?;
+            items.extend(page);
+            next = match next_page_url(&headers) {
+                Some(link) => Some(parse_url(&link)?),
+                None => None,
+            };
+        }
+        Ok(items)
+    }
+
+    // graphql sends the query to the GraphQL API and returns the `data` of the response.
+    // It shares the authentication, retries and rate limit tracking of the REST requests.
+    pub async fn graphql<T: serde::de::DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T, GitHubError> {
+        let url = parse_url(&graphql_url(&self.api_url))?;
+        let req = self
+            .request(reqwest::Method::POST, url)
+            .json(&serde_json::json!({ "query": query, "variables": variables }));
+        // queries only read, so they are retried like GET requests
+        let (_, _, body) = self.send_with_headers(req, true).await?;
+        let response: GraphQLResponse<T> = serde_json::from_str(&body)?;
+        if !response.errors.is_empty() {
+            return Err(GitHubError::from_graphql(response.errors));
+        }
+        response.data.ok_or_else(|| GitHubError::GraphQL {
+            messages: vec!["response has no data".to_string()],
+        })
+    }
+
+    // issue_url builds the URL of an issue sub-resource, e.g. `/issues/1/labels/bug`,
+    // percent-encoding each segment since label names may contain spaces.
+    fn issue_url(&self, issue_number: u32, segments: &[&str]) -> Result<reqwest::Url, GitHubError> {
+        let mut url = parse_url(&self.issues_url())?;
+        url.path_segments_mut()
+            .map_err(|_| GitHubError::Request("API URL cannot be a base".to_string()))?
+            .push(&issue_number.to_string())
+            .extend(segments);
+        Ok(url)
+    }
+}
+
+#[async_trait::async_trait]
+impl GitHub for Client {
+    async fn get_open_issues(
+        &self,
+        include_pull_requests: bool,
+    ) -> Result<Vec<Issue>, GitHubError> {
+        let mut url = parse_url(&self.issues_url())?;
+        url.query_pairs_mut().append_pair("state", "open");
+        let mut issues: Vec<Issue> = self.get_all_pages(url).await?;
+        if !include_pull_requests {
+            issues.retain(|issue| !issue.is_pull_request());
+        }
+        Ok(issues)
+    }
 
-        let response: Vec<Issue> = serde_json::from_str(&body)?;
+    async fn get_issue(&self, issue_number: u32) -> Result<Issue, GitHubError> {
+        let url = self.issue_url(issue_number, &[])?;
+        let (_, body) = self.get(url).await?;
+        let response: Issue = serde_json::from_str(&body)?;
         Ok(response)
     }
+
+    async fn get_issue_timeline(
+        &self,
+        issue_number: u32,
+    ) -> Result<Vec<TimelineEvent>, GitHubError> {
+        let url = self.issue_url(issue_number, &["timeline"])?;
+        self.get_all_pages(url).await
+    }
+
+    async fn get_issue_comments(&self, issue_number: u32) -> Result<Vec<Comment>, GitHubError> {
+        let url = self.issue_url(issue_number, &["comments"])?;
+        self.get_all_pages(url).await
+    }
+
+    async fn get_collaborator_permission(&self, login: &str) -> Result<Permission, GitHubError> {
+        let mut url = parse_url(&format!(
+            "{}/repos/{}/{}/collaborators",
+            self.api_url, self.repo_owner, self.repo_name,
+        ))?;
+        url.path_segments_mut()
+            .map_err(|_| GitHubError::Request("API URL cannot be a base".to_string()))?
+            .extend([login, "permission"]);
+        let (_, body) = self.get(url).await?;
+        let response: CollaboratorPermission = serde_json::from_str(&body)?;
+        Ok(response.permission())
+    }
+
+    async fn add_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError> {
+        let url = self.issue_url(issue_number, &["labels"])?;
+        let req = self
+            .request(reqwest::Method::POST, url)
+            .json(&serde_json::json!({ "labels": labels }));
+        self.send(req).await?;
+        Ok(())
+    }
+
+    async fn remove_label(&self, issue_number: u32, label: &str) -> Result<(), GitHubError> {
+        let url = self.issue_url(issue_number, &["labels", label])?;
+        self.send(self.request(reqwest::Method::DELETE, url))
+            .await?;
+        Ok(())
+    }
+
+    async fn set_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError> {
+        let url = self.issue_url(issue_number, &["labels"])?;
+        let req = self
+            .request(reqwest::Method::PUT, url)
+            .json(&serde_json::json!({ "labels": labels }));
+        self.send(req).await?;
+        Ok(())
+    }
+
+    async fn post_comment(&self, issue_number: u32, body: &str) -> Result<(), GitHubError> {
+        let url = self.issue_url(issue_number, &["comments"])?;
+        let req = self
+            .request(reqwest::Method::POST, url)
+            .json(&serde_json::json!({ "body": body }));
+        self.send(req).await?;
+        Ok(())
+    }
+
+    async fn close_issue(&self, issue_number: u32) -> Result<(), GitHubError> {
+        let url = self.issue_url(issue_number, &[])?;
+        let req = self
+            .request(reqwest::Method::PATCH, url)
+            .json(&serde_json::json!({ "state": "closed" }));
+        self.send(req).await?;
+        Ok(())
+    }
+
+    async fn get_file(&self, path: &str) -> Result<Option<RepoFile>, GitHubError> {
+        let mut url = parse_url(&format!(
+            "{}/repos/{}/{}/contents",
+            self.api_url, self.repo_owner, self.repo_name,
+        ))?;
+        url.path_segments_mut()
+            .map_err(|_| GitHubError::Request("API URL cannot be a base".to_string()))?
+            .extend(path.split('/'));
+        let body = match self.get(url).await {
+            Ok((_, body)) => body,
+            Err(GitHubError::NotFound { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let response: FileContents = serde_json::from_str(&body)?;
+        Ok(Some(response.decode()?))
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit.get()
+    }
+}
+
+// CollaboratorPermission is the response of the collaborator permission API.
+// The legacy `permission` field maps maintain to write and triage to read,
+// so the role name is preferred, unless it is a custom role.
+#[derive(serde::Deserialize)]
+struct CollaboratorPermission {
+    permission: Permission,
+    role_name: String,
+}
+
+impl CollaboratorPermission {
+    fn permission(&self) -> Permission {
+        serde_json::from_value(serde_json::Value::String(self.role_name.clone()))
+            .unwrap_or(self.permission)
+    }
+}
+
+// FileContents is the response of the repository contents API for a file.
+// Directories are returned as arrays and fail to deserialize.
+#[derive(serde::Deserialize)]
+struct FileContents {
+    path: String,
+    sha: String,
+    content: String,
+    encoding: String,
 }
 
-#[derive(Debug)]
-struct GitHubError {
-    pub code: u16,
-    pub message: String,
+impl FileContents {
+    // decode returns the file with its content decoded from base64,
+    // which the API wraps at 60 characters.
+    fn decode(self) -> Result<RepoFile, GitHubError> {
+        use base64::Engine;
+        use serde::de::Error;
+        if self.encoding != "base64" {
+            let message = format!("unsupported encoding {} of {}", self.encoding, self.path);
+            return Err(serde_json::Error::custom(message).into());
+        }
+        let encoded: String = self.content.split_whitespace().collect();
+        let content = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| {
+                serde_json::Error::custom(format!("{} is not base64-encoded UTF-8", self.path))
+            })?;
+        Ok(RepoFile {
+            path: self.path,
+            sha: self.sha,
+            content,
+        })
+    }
 }
 
-impl std::error::Error for GitHubError {}
+#[derive(serde::Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
 
-impl std::fmt::Display for GitHubError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Status: {} - {}", self.code, self.message)
+// graphql_url returns the GraphQL endpoint for the REST API URL: GitHub.com serves
+// it at https://api.github.com/graphql, GitHub Enterprise Server at /api/graphql
+// next to the REST API at /api/v3.
+fn graphql_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    match api_url.strip_suffix("/v3") {
+        Some(base) => format!("{}/graphql", base),
+        None => format!("{}/graphql", api_url),
     }
 }
 
+// next_page_url extracts the URL with rel="next" from the Link header, e.g.
+// `<https://api.github.com/...&page=2>; rel="next", <https://api.github.com/...&page=5>; rel="last"`.
+fn next_page_url(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        let is_next = params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#);
+        let url = url.trim().strip_prefix('<')?.strip_suffix('>')?;
+        is_next.then(|| url.to_string())
+    })
+}
+
+fn parse_url(url: &str) -> Result<reqwest::Url, GitHubError> {
+    reqwest::Url::parse(url).map_err(|err| GitHubError::Request(format!("{}: {}", err, url)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use wiremock::matchers::{method, path, query_param};
+    use crate::github::{AuthorAssociation, IssueState};
+    use chrono::{TimeZone, Utc};
+    use wiremock::matchers::{
+        body_json, header, method, path, query_param, query_param_is_missing,
+    };
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
@@ -68,7 +421,31 @@ mod tests {
                     "url": "https://api.github.com/repos/yurishkuro/rustybot/issues/1",
                     "user": {
                         "login": "yurishkuro"
-                    }
+                    },
+                    "labels": [
+                        {
+                            "name": "needs-triage",
+                            "color": "ededed",
+                            "description": "Issue is pending triage"
+                        }
+                    ],
+                    "state": "open",
+                    "created_at": "2024-07-01T10:00:00Z",
+                    "updated_at": "2024-07-02T11:30:00Z",
+                    "closed_at": null,
+                    "assignees": [
+                        {
+                            "login": "octocat"
+                        }
+                    ],
+                    "milestone": {
+                        "number": 1,
+                        "title": "v1.0",
+                        "state": "open",
+                        "due_on": null
+                    },
+                    "comments": 3,
+                    "author_association": "FIRST_TIME_CONTRIBUTOR"
                 },
                 {
                     "number": 2,
@@ -77,6 +454,36 @@ mod tests {
                     "url": "https://api.github.com/repos/yurishkuro/rustybot/issues/2",
                     "user": {
                         "login": "yurishkuro"
+                    },
+                    "labels": [],
+                    "state": "open",
+                    "created_at": "2024-07-01T10:00:00Z",
+                    "updated_at": "2024-07-02T10:00:00Z",
+                    "closed_at": null,
+                    "assignees": [],
+                    "milestone": null,
+                    "comments": 0,
+                    "author_association": "OWNER"
+                },
+                {
+                    "number": 3,
+                    "title": "Pull request 3",
+                    "body": "Resolves #2",
+                    "url": "https://api.github.com/repos/yurishkuro/rustybot/issues/3",
+                    "user": {
+                        "login": "yurishkuro"
+                    },
+                    "labels": [],
+                    "state": "open",
+                    "created_at": "2024-07-01T10:00:00Z",
+                    "updated_at": "2024-07-02T10:00:00Z",
+                    "closed_at": null,
+                    "assignees": [],
+                    "milestone": null,
+                    "comments": 0,
+                    "author_association": "OWNER",
+                    "pull_request": {
+                        "url": "https://api.github.com/repos/yurishkuro/rustybot/pulls/3"
                     }
                 }
             ]
@@ -91,17 +498,646 @@ mod tests {
 
         let gh_client = Client {
             api_url: mock_server.uri(),
-            token: String::from(""),
+            auth: Auth::Token(String::new()),
             repo_owner: String::from("yurishkuro"),
             repo_name: String::from("rustybot"),
+            per_page: DEFAULT_PER_PAGE,
+            retry: RetryPolicy::default(),
+            rate_limit: Default::default(),
+            cache: None,
         };
-        let result = gh_client.get_open_issues().await;
+        let result = gh_client.get_open_issues(false).await;
         assert!(result.is_ok());
         let issues = result.unwrap();
         assert_eq!(issues.len(), 2);
         assert_eq!(issues[0].number, 1);
         assert_eq!(issues[0].title, "Issue 1");
+        assert!(issues[0].has_label("needs-triage"));
+        assert_eq!(issues[0].labels[0].color, "ededed");
+        assert_eq!(issues[0].state, IssueState::Open);
+        assert_eq!(
+            issues[0].updated_at,
+            Utc.with_ymd_and_hms(2024, 7, 2, 11, 30, 0).unwrap()
+        );
+        assert!(issues[0].closed_at.is_none());
+        assert_eq!(issues[0].assignees[0].login, "octocat");
+        assert_eq!(issues[0].milestone.as_ref().unwrap().title, "v1.0");
+        assert_eq!(issues[0].comments, 3);
+        assert_eq!(
+            issues[0].author_association,
+            AuthorAssociation::FirstTimeContributor
+        );
         assert_eq!(issues[1].number, 2);
         assert_eq!(issues[1].title, "Issue 2");
+        assert!(issues[1].labels.is_empty());
+        assert!(!issues[1].is_pull_request());
+
+        let issues = gh_client.get_open_issues(true).await.unwrap();
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[2].number, 3);
+        assert!(issues[2].is_pull_request());
+    }
+
+    #[tokio::test]
+    async fn test_get_open_issues_pagination() {
+        let mock_server = MockServer::start().await;
+        let issue = |number: u32| {
+            serde_json::json!({
+                "number": number,
+                "title": format!("Issue {}", number),
+                "body": null,
+                "url": format!("https://api.github.com/repos/yurishkuro/rustybot/issues/{}", number),
+                "user": { "login": "yurishkuro" },
+                "labels": [],
+                "state": "open",
+                "created_at": "2024-07-01T10:00:00Z",
+                "updated_at": "2024-07-01T10:00:00Z",
+                "closed_at": null,
+                "assignees": [],
+                "milestone": null,
+                "comments": 0,
+                "author_association": "MEMBER"
+            })
+        };
+        let page_url = |page: u32| {
+            format!(
+                "{}/repos/yurishkuro/rustybot/issues?state=open&per_page=2&page={}",
+                mock_server.uri(),
+                page
+            )
+        };
+        let pages = [
+            (vec![issue(1), issue(2)], Some(page_url(2))),
+            (vec![issue(3), issue(4)], Some(page_url(3))),
+            (vec![issue(5)], None),
+        ];
+        for (i, (items, next)) in pages.into_iter().enumerate() {
+            let mut response = ResponseTemplate::new(200).set_body_json(items);
+            if let Some(next) = next {
+                let link = format!(r#"<{}>; rel="next", <{}>; rel="last""#, next, page_url(3));
+                response = response.insert_header("Link", link.as_str());
+            }
+            let page = i + 1;
+            let mock = Mock::given(method("GET"))
+                .and(path("/repos/yurishkuro/rustybot/issues"))
+                .and(query_param("state", "open"))
+                .and(query_param("per_page", "2"));
+            let mock = if page == 1 {
+                mock.and(query_param_is_missing("page"))
+            } else {
+                mock.and(query_param("page", page.to_string()))
+            };
+            mock.respond_with(response)
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let gh_client = Client {
+            per_page: 2,
+            ..client(&mock_server)
+        };
+        let issues = gh_client.get_open_issues(false).await.unwrap();
+        let numbers: Vec<u32> = issues.iter().map(|issue| issue.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_next_page_url() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(next_page_url(&headers), None);
+
+        headers.insert(
+            reqwest::header::LINK,
+            r#"<https://api.github.com/issues?page=1>; rel="prev", <https://api.github.com/issues?page=3>; rel="next""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://api.github.com/issues?page=3")
+        );
+
+        headers.insert(
+            reqwest::header::LINK,
+            r#"<https://api.github.com/issues?page=1>; rel="first""#
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(next_page_url(&headers), None);
+    }
+
+    #[test]
+    fn test_graphql_url() {
+        assert_eq!(
+            graphql_url("https://api.github.com"),
+            "https://api.github.com/graphql"
+        );
+        assert_eq!(
+            graphql_url("https://github.example.com/api/v3/"),
+            "https://github.example.com/api/graphql"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_issue() {
+        let mock_server = MockServer::start().await;
+        let body = r#"
+            {
+                "number": 42,
+                "title": "Issue 42",
+                "body": null,
+                "url": "https://api.github.com/repos/yurishkuro/rustybot/issues/42",
+                "user": {
+                    "login": "yurishkuro"
+                },
+                "labels": [
+                    {
+                        "name": "help-wanted",
+                        "color": "008672",
+                        "description": null
+                    }
+                ],
+                "state": "closed",
+                "created_at": "2024-06-01T10:00:00Z",
+                "updated_at": "2024-07-01T10:00:00Z",
+                "closed_at": "2024-07-01T10:00:00Z",
+                "assignees": [],
+                "milestone": null,
+                "comments": 0,
+                "author_association": "NONE"
+            }
+        "#;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/42"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+
+        let issue = client(&mock_server).get_issue(42).await.unwrap();
+        assert_eq!(issue.number, 42);
+        assert!(issue.body.is_none());
+        assert!(issue.has_label("help-wanted"));
+        assert_eq!(issue.state, IssueState::Closed);
+        assert_eq!(issue.closed_at, Some(issue.updated_at));
+        assert_eq!(issue.author_association, AuthorAssociation::None);
+    }
+
+    #[tokio::test]
+    async fn test_get_issue_timeline() {
+        let mock_server = MockServer::start().await;
+        let body = r#"
+            [
+                {
+                    "event": "labeled",
+                    "actor": {
+                        "login": "yurishkuro"
+                    },
+                    "created_at": "2024-07-01T10:00:00Z",
+                    "label": {
+                        "name": "needs-info",
+                        "color": "ededed"
+                    }
+                },
+                {
+                    "event": "commented",
+                    "user": {
+                        "login": "octocat"
+                    },
+                    "created_at": "2024-07-02T10:00:00Z",
+                    "body": "Here is more info"
+                },
+                {
+                    "event": "cross-referenced",
+                    "actor": {
+                        "login": "octocat"
+                    },
+                    "created_at": "2024-07-02T12:00:00Z",
+                    "updated_at": "2024-07-02T12:00:00Z",
+                    "source": {
+                        "type": "issue",
+                        "issue": {
+                            "number": 43,
+                            "url": "https://api.github.com/repos/yurishkuro/rustybot/issues/43",
+                            "state": "closed",
+                            "body": "Fixes #42",
+                            "pull_request": {
+                                "url": "https://api.github.com/repos/yurishkuro/rustybot/pulls/43",
+                                "merged_at": "2024-07-03T09:00:00Z"
+                            }
+                        }
+                    }
+                },
+                {
+                    "event": "unlabeled",
+                    "actor": {
+                        "login": "yurishkuro"
+                    },
+                    "created_at": "2024-07-03T10:00:00Z",
+                    "label": {
+                        "name": "needs-info",
+                        "color": "ededed"
+                    }
+                },
+                {
+                    "event": "labeled",
+                    "actor": null,
+                    "created_at": "2024-07-04T10:00:00Z",
+                    "label": {
+                        "name": "stale",
+                        "color": "ededed"
+                    }
+                },
+                {
+                    "event": "reopened",
+                    "actor": null,
+                    "created_at": "2024-07-05T10:00:00Z"
+                },
+                {
+                    "event": "connected",
+                    "actor": null,
+                    "created_at": "2024-07-06T10:00:00Z"
+                }
+            ]
+        "#;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/42/timeline"))
+            .and(query_param("per_page", "100"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+
+        let timeline = client(&mock_server).get_issue_timeline(42).await.unwrap();
+        assert_eq!(timeline.len(), 7);
+        assert!(matches!(
+            timeline[0],
+            TimelineEvent::Labeled { ref label, .. } if label.name == "needs-info"
+        ));
+        assert!(matches!(timeline[1], TimelineEvent::Other));
+        let TimelineEvent::CrossReferenced { ref source, .. } = timeline[2] else {
+            panic!("expecting cross-referenced event, got {:?}", timeline[2]);
+        };
+        assert_eq!(source.issue.number, 43);
+        assert!(source
+            .issue
+            .pull_request
+            .as_ref()
+            .unwrap()
+            .merged_at
+            .is_some());
+        assert!(matches!(timeline[3], TimelineEvent::Unlabeled { .. }));
+        // the actor of an event by a deleted account is null
+        assert!(matches!(
+            timeline[4],
+            TimelineEvent::Labeled { ref actor, .. } if actor.login.is_empty()
+        ));
+        assert!(matches!(
+            timeline[5],
+            TimelineEvent::Reopened { ref actor, .. } if actor.login.is_empty()
+        ));
+        assert!(matches!(
+            timeline[6],
+            TimelineEvent::Connected { ref actor, .. } if actor.login.is_empty()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_issue_comments() {
+        let mock_server = MockServer::start().await;
+        let body = r#"
+            [
+                {
+                    "id": 1001,
+                    "user": {
+                        "login": "yurishkuro"
+                    },
+                    "body": "/in-progress",
+                    "created_at": "2024-07-01T10:00:00Z",
+                    "updated_at": "2024-07-01T10:00:00Z",
+                    "author_association": "OWNER"
+                }
+            ]
+        "#;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/42/comments"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+
+        let comments = client(&mock_server).get_issue_comments(42).await.unwrap();
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].user.login, "yurishkuro");
+        assert_eq!(comments[0].body, "/in-progress");
+        assert_eq!(comments[0].author_association, AuthorAssociation::Owner);
+    }
+
+    #[tokio::test]
+    async fn test_get_collaborator_permission() {
+        let mock_server = MockServer::start().await;
+        for (login, permission, role_name) in [
+            ("triager", "read", "triage"),
+            ("maintainer", "write", "maintain"),
+            ("custom", "write", "release-manager"),
+            ("stranger", "none", "none"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/yurishkuro/rustybot/collaborators/{}/permission",
+                    login
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "permission": permission,
+                    "role_name": role_name,
+                    "user": { "login": login }
+                })))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let gh_client = client(&mock_server);
+        for (login, expected) in [
+            ("triager", Permission::Triage),
+            ("maintainer", Permission::Maintain),
+            ("custom", Permission::Write),
+            ("stranger", Permission::None),
+        ] {
+            let permission = gh_client.get_collaborator_permission(login).await.unwrap();
+            assert_eq!(permission, expected, "{}", login);
+        }
+    }
+
+    fn client(mock_server: &MockServer) -> Client {
+        Client {
+            api_url: mock_server.uri(),
+            auth: Auth::Token(String::from("secret")),
+            repo_owner: String::from("yurishkuro"),
+            repo_name: String::from("rustybot"),
+            per_page: DEFAULT_PER_PAGE,
+            retry: RetryPolicy::default(),
+            rate_limit: Default::default(),
+            cache: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_labels() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/labels"))
+            .and(header("Authorization", "Bearer secret"))
+            .and(body_json(serde_json::json!({ "labels": ["help wanted"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(
+                "/repos/yurishkuro/rustybot/issues/1/labels/help%20wanted",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/labels"))
+            .and(body_json(
+                serde_json::json!({ "labels": ["bug", "needs-info"] }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let gh_client = client(&mock_server);
+        gh_client
+            .add_labels(1, &[String::from("help wanted")])
+            .await
+            .unwrap();
+        gh_client.remove_label(1, "help wanted").await.unwrap();
+        gh_client
+            .set_labels(1, &[String::from("bug"), String::from("needs-info")])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_comment_and_close() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/comments"))
+            .and(body_json(serde_json::json!({ "body": "Closing." })))
+            .respond_with(ResponseTemplate::new(201).set_body_string("{}"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1"))
+            .and(body_json(serde_json::json!({ "state": "closed" })))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let gh_client = client(&mock_server);
+        gh_client.post_comment(1, "Closing.").await.unwrap();
+        gh_client.close_issue(1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("Not Found"))
+            .mount(&mock_server)
+            .await;
+
+        let err = client(&mock_server).close_issue(1).await.unwrap_err();
+        assert!(matches!(err, GitHubError::NotFound { status: 404, .. }));
+        assert_eq!(err.to_string(), "Status: 404 - Not Found");
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/comments"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/comments"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("X-RateLimit-Limit", "5000")
+                    .insert_header("X-RateLimit-Remaining", "4990")
+                    .insert_header("X-RateLimit-Reset", "1372700873")
+                    .set_body_string("[]"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut client = client(&mock_server);
+        client.retry.initial_backoff = std::time::Duration::from_millis(1);
+        let comments = client.get_issue_comments(1).await.unwrap();
+        assert!(comments.is_empty());
+        assert_eq!(client.rate_limit.get().unwrap().remaining, 4990);
+
+        // gives up after max_retries
+        client.retry.max_retries = 1;
+        mock_server.reset().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Unavailable"))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        let err = client.get_issue_comments(1).await.unwrap_err();
+        assert_eq!(err.to_string(), "Status: 503 - Unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_of_post() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/comments"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("Bad Gateway"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut client = client(&mock_server);
+        client.retry.initial_backoff = std::time::Duration::from_millis(1);
+        // the comment may have been stored before the error
+        let err = client.post_comment(1, "Closing.").await.unwrap_err();
+        assert_eq!(err.to_string(), "Status: 502 - Bad Gateway");
+
+        // rate limited requests were not applied, so they are retried
+        mock_server.reset().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("Retry-After", "0")
+                    .set_body_string("Too Many Requests"),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(201).set_body_string("{}"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        client.post_comment(1, "Closing.").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_exceeded() {
+        let mock_server = MockServer::start().await;
+        let reset = Utc::now().timestamp() + 3600;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("X-RateLimit-Limit", "5000")
+                    .insert_header("X-RateLimit-Remaining", "0")
+                    .insert_header("X-RateLimit-Reset", reset.to_string().as_str())
+                    .set_body_string("API rate limit exceeded"),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = client(&mock_server);
+        let err = client.get_issue(1).await.unwrap_err();
+        assert!(matches!(err, GitHubError::RateLimited { reset: Some(_) }));
+        assert!(
+            err.to_string()
+                .starts_with("rate limit exceeded, resets at"),
+            "{}",
+            err
+        );
+        // no more requests are sent until the limit resets
+        assert!(client.get_issue(2).await.is_err());
+        assert!(client
+            .rate_limit
+            .exceeded(&client.retry, Utc::now())
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        let mock_server = MockServer::start().await;
+        let comments = r#"[{"id": 1, "user": {"login": "user1"}, "body": "hello",
+            "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-01T00:00:00Z",
+            "author_association": "NONE"}]"#;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/comments"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/issues/1/comments"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(comments),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let gh_client = Client {
+            cache: Some(ResponseCache {
+                dir: dir.path().to_path_buf(),
+            }),
+            ..client(&mock_server)
+        };
+        let first = gh_client.get_issue_comments(1).await.unwrap();
+        assert_eq!(first.len(), 1);
+        // the second request is answered with 304 and served from the cache
+        let second = gh_client.get_issue_comments(1).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].body, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_get_file() {
+        let mock_server = MockServer::start().await;
+        // "states: []\n" wrapped like the API does
+        let body = r#"{
+            "type": "file",
+            "encoding": "base64",
+            "path": ".github/issue-manager.yml",
+            "sha": "3d21ec53a331a6f037a91c368710b99387d012c1",
+            "content": "c3RhdGVz\nOiBbXQo=\n"
+        }"#;
+        Mock::given(method("GET"))
+            .and(path(
+                "/repos/yurishkuro/rustybot/contents/.github/issue-manager.yml",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/yurishkuro/rustybot/contents/missing.yml"))
+            .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"message": "Not Found"}"#))
+            .mount(&mock_server)
+            .await;
+
+        let gh_client = client(&mock_server);
+        let file = gh_client
+            .get_file(".github/issue-manager.yml")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(file.sha, "3d21ec53a331a6f037a91c368710b99387d012c1");
+        assert_eq!(file.content, "states: []\n");
+        assert_eq!(gh_client.get_file("missing.yml").await.unwrap(), None);
     }
 }
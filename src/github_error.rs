@@ -0,0 +1,321 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+// GitHubError is the error returned by the GitHub API client. The variants
+// distinguish failures that only affect one issue from those that affect
+// every request, see recovery.
+#[derive(Debug)]
+pub enum GitHubError {
+    // the token is missing or invalid (401), or the app cannot get a token (403)
+    Auth {
+        status: u16,
+        message: String,
+    },
+    // the request is not allowed on this resource (403), e.g. commenting on a
+    // locked issue or a token without access to the repository of the issue
+    Forbidden {
+        message: String,
+    },
+    // the resource does not exist or is not visible to the token (404, 410)
+    NotFound {
+        status: u16,
+        message: String,
+    },
+    // the primary rate limit is exhausted or a secondary rate limit was hit,
+    // reset is unknown for secondary rate limits
+    RateLimited {
+        reset: Option<DateTime<Utc>>,
+    },
+    // the request was rejected as invalid (422), e.g. a label name is too long
+    Validation {
+        message: String,
+        errors: Vec<ValidationError>,
+    },
+    // any other unsuccessful response
+    Status {
+        status: u16,
+        message: String,
+    },
+    // the GraphQL API returned errors for the query
+    GraphQL {
+        messages: Vec<String>,
+    },
+    // the request could not be sent or the response could not be read
+    Network(reqwest::Error),
+    // the response body is not what the client expects
+    Decode(serde_json::Error),
+    // the request could not be built, e.g. from an invalid API URL
+    Request(String),
+}
+
+// ValidationError is an entry of the `errors` array of a 422 response.
+// See https://docs.github.com/en/rest/using-the-rest-api/troubleshooting-the-rest-api
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct ValidationError {
+    #[serde(default)]
+    pub resource: String,
+    #[serde(default)]
+    pub field: String,
+    #[serde(default)]
+    pub code: String,
+    pub message: Option<String>,
+}
+
+// Recovery is how processing should continue after an error.
+#[derive(Debug, PartialEq)]
+pub enum Recovery {
+    // skip the issue and continue with the next one
+    Skip,
+    // the error is transient, the issue can be processed again
+    Retry,
+    // every other request would fail as well, stop processing
+    Abort,
+}
+
+// ErrorBody is the JSON body of unsuccessful responses.
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+    #[serde(default)]
+    errors: Vec<serde_json::Value>,
+}
+
+impl GitHubError {
+    // from_response classifies an unsuccessful response by its status and body.
+    // exhausted_until is the reset time of the primary rate limit if the response
+    // reports it as exhausted.
+    pub fn from_response(
+        status: u16,
+        body: &str,
+        exhausted_until: Option<DateTime<Utc>>,
+    ) -> GitHubError {
+        let parsed = serde_json::from_str::<ErrorBody>(body).ok();
+        let message = match &parsed {
+            Some(parsed) => parsed.message.clone(),
+            None => body.to_string(),
+        };
+        match status {
+            403 | 429 if exhausted_until.is_some() => GitHubError::RateLimited {
+                reset: exhausted_until,
+            },
+            403 | 429 if message.to_lowercase().contains("rate limit") => {
+                GitHubError::RateLimited { reset: None }
+            }
+            401 => GitHubError::Auth { status, message },
+            403 => GitHubError::Forbidden { message },
+            404 | 410 => GitHubError::NotFound { status, message },
+            422 => GitHubError::Validation {
+                message,
+                errors: parsed
+                    .map(|parsed| parsed.errors)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(ValidationError::from_value)
+                    .collect(),
+            },
+            _ => GitHubError::Status { status, message },
+        }
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            GitHubError::Auth { .. } | GitHubError::RateLimited { .. } => Recovery::Abort,
+            GitHubError::Network(_) => Recovery::Retry,
+            GitHubError::Status { status, .. } if *status >= 500 => Recovery::Retry,
+            GitHubError::Forbidden { .. }
+            | GitHubError::NotFound { .. }
+            | GitHubError::Validation { .. }
+            | GitHubError::GraphQL { .. }
+            | GitHubError::Status { .. }
+            | GitHubError::Decode(_)
+            | GitHubError::Request(_) => Recovery::Skip,
+        }
+    }
+}
+
+// GraphQLError is an entry of the `errors` array of a GraphQL response, which
+// is returned with status 200 even if the query failed.
+#[derive(Deserialize, Debug)]
+pub struct GraphQLError {
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    pub message: String,
+}
+
+impl GitHubError {
+    // from_graphql classifies the errors of a GraphQL response.
+    pub fn from_graphql(errors: Vec<GraphQLError>) -> GitHubError {
+        if errors.iter().any(|err| err.kind == "RATE_LIMITED") {
+            return GitHubError::RateLimited { reset: None };
+        }
+        let messages = errors.into_iter().map(|err| err.message).collect();
+        GitHubError::GraphQL { messages }
+    }
+}
+
+impl ValidationError {
+    // from_value decodes an entry of the `errors` array, which some endpoints
+    // return as plain strings rather than objects.
+    fn from_value(value: serde_json::Value) -> ValidationError {
+        match value {
+            serde_json::Value::String(message) => ValidationError {
+                message: Some(message),
+                ..Default::default()
+            },
+            value => serde_json::from_value(value.clone()).unwrap_or_else(|_| ValidationError {
+                message: Some(value.to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl std::error::Error for GitHubError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GitHubError::Network(err) => Some(err),
+            GitHubError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for GitHubError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GitHubError::Auth { status, message } => {
+                write!(f, "authentication failed: Status: {} - {}", status, message)
+            }
+            GitHubError::Forbidden { message } => write!(f, "forbidden: Status: 403 - {}", message),
+            GitHubError::NotFound { status, message } | GitHubError::Status { status, message } => {
+                write!(f, "Status: {} - {}", status, message)
+            }
+            GitHubError::RateLimited { reset: Some(reset) } => {
+                write!(f, "rate limit exceeded, resets at {}", reset)
+            }
+            GitHubError::RateLimited { reset: None } => write!(f, "secondary rate limit exceeded"),
+            GitHubError::Validation { message, errors } => {
+                write!(f, "Status: 422 - {}", message)?;
+                for error in errors {
+                    write!(f, "; {}", error)?;
+                }
+                Ok(())
+            }
+            GitHubError::GraphQL { messages } => {
+                write!(f, "GraphQL errors: {}", messages.join("; "))
+            }
+            GitHubError::Network(err) => write!(f, "network error: {}", err),
+            GitHubError::Decode(err) => write!(f, "cannot decode response: {}", err),
+            GitHubError::Request(message) => write!(f, "invalid request: {}", message),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(message) = &self.message {
+            return write!(f, "{}", message);
+        }
+        write!(f, "{} {} {}", self.resource, self.field, self.code)
+    }
+}
+
+impl From<reqwest::Error> for GitHubError {
+    fn from(err: reqwest::Error) -> Self {
+        GitHubError::Network(err)
+    }
+}
+
+impl From<serde_json::Error> for GitHubError {
+    fn from(err: serde_json::Error) -> Self {
+        GitHubError::Decode(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_response() {
+        let err = GitHubError::from_response(401, r#"{"message": "Bad credentials"}"#, None);
+        assert!(matches!(err, GitHubError::Auth { status: 401, .. }));
+        assert_eq!(
+            err.to_string(),
+            "authentication failed: Status: 401 - Bad credentials"
+        );
+        assert_eq!(err.recovery(), Recovery::Abort);
+
+        let err = GitHubError::from_response(404, "Not Found", None);
+        assert_eq!(err.to_string(), "Status: 404 - Not Found");
+        assert_eq!(err.recovery(), Recovery::Skip);
+
+        let body = r#"{
+            "message": "Validation Failed",
+            "errors": [
+                { "resource": "Label", "code": "invalid", "field": "name" },
+                "name is too long (maximum is 50 characters)"
+            ],
+            "documentation_url": "https://docs.github.com/rest/issues/labels#add-labels-to-an-issue"
+        }"#;
+        let err = GitHubError::from_response(422, body, None);
+        let GitHubError::Validation { errors, .. } = &err else {
+            panic!("expecting validation error, got {:?}", err);
+        };
+        assert_eq!(
+            errors[0],
+            ValidationError {
+                resource: "Label".to_string(),
+                field: "name".to_string(),
+                code: "invalid".to_string(),
+                message: None,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Status: 422 - Validation Failed; Label name invalid; \
+             name is too long (maximum is 50 characters)"
+        );
+        assert_eq!(err.recovery(), Recovery::Skip);
+
+        let body = r#"{"message": "You have exceeded a secondary rate limit."}"#;
+        let err = GitHubError::from_response(403, body, None);
+        assert!(matches!(err, GitHubError::RateLimited { reset: None }));
+        assert_eq!(err.recovery(), Recovery::Abort);
+        let reset = Utc::now();
+        let err = GitHubError::from_response(403, "API rate limit exceeded", Some(reset));
+        assert!(matches!(err, GitHubError::RateLimited { reset: Some(r) } if r == reset));
+        // forbidden for other reasons, e.g. a locked issue, only affects the issue
+        let err = GitHubError::from_response(403, "Resource not accessible", None);
+        assert!(matches!(err, GitHubError::Forbidden { .. }));
+        assert_eq!(
+            err.to_string(),
+            "forbidden: Status: 403 - Resource not accessible"
+        );
+        assert_eq!(err.recovery(), Recovery::Skip);
+
+        let errors = vec![GraphQLError {
+            kind: "NOT_FOUND".to_string(),
+            message: "Could not resolve to a Repository".to_string(),
+        }];
+        let err = GitHubError::from_graphql(errors);
+        assert_eq!(
+            err.to_string(),
+            "GraphQL errors: Could not resolve to a Repository"
+        );
+        assert_eq!(err.recovery(), Recovery::Skip);
+        let errors = vec![GraphQLError {
+            kind: "RATE_LIMITED".to_string(),
+            message: "API rate limit exceeded".to_string(),
+        }];
+        assert_eq!(
+            GitHubError::from_graphql(errors).recovery(),
+            Recovery::Abort
+        );
+
+        let err = GitHubError::from_response(502, "Bad Gateway", None);
+        assert_eq!(err.recovery(), Recovery::Retry);
+        let err = GitHubError::from_response(409, "Conflict", None);
+        assert_eq!(err.recovery(), Recovery::Skip);
+    }
+}
@@ -0,0 +1,911 @@
+use super::github::{
+    AuthorAssociation, Comment, CrossReferenceSource, GitHub, Issue, IssueState, Label,
+    LinkedIssue, Milestone, Permission, PullRequestRef, RepoFile, TimelineEvent, User,
+};
+use super::github_client::Client;
+use super::github_error::GitHubError;
+use super::rate_limit::RateLimit;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// GraphQLClient implements GitHub with the GraphQL API. Listing open issues
+// fetches their labels, comments and timeline events in the same queries, and
+// keeps comments and timelines until the engine asks for them, instead of
+// making two more REST calls per issue. Mutations and collaborator permissions
+// go through the REST client, which also provides authentication and retries.
+// See https://docs.github.com/en/graphql
+pub struct GraphQLClient {
+    pub rest: Client,
+    // comments and timelines fetched with the issues, by issue number
+    prefetched: Mutex<HashMap<u32, Prefetched>>,
+}
+
+// Prefetched holds the nested connections fetched with an issue. A connection
+// that has more pages than fit in the issue query is None and fetched separately.
+#[derive(Default)]
+struct Prefetched {
+    comments: Option<Vec<Comment>>,
+    timeline: Option<Vec<TimelineEvent>>,
+}
+
+// Page size of the nested connections, the maximum allowed by GitHub.
+const NESTED_PAGE_SIZE: u32 = 100;
+
+// Page size of the issue queries. Each issue brings up to NESTED_PAGE_SIZE nodes
+// of every nested connection, so pages are smaller than for REST lists.
+const MAX_ISSUES_PER_QUERY: u32 = 25;
+
+// The timeline events used by the state machine, see TimelineEvent.
+const TIMELINE_ITEM_TYPES: &str = "[LABELED_EVENT, UNLABELED_EVENT, RENAMED_TITLE_EVENT, \
+     REOPENED_EVENT, CROSS_REFERENCED_EVENT, CONNECTED_EVENT, DISCONNECTED_EVENT]";
+
+const COMMENT_PAGE: &str = "
+    totalCount
+    pageInfo { hasNextPage endCursor }
+    nodes { databaseId author { login } body createdAt updatedAt authorAssociation }";
+
+const TIMELINE_PAGE: &str = "
+    pageInfo { hasNextPage endCursor }
+    nodes {
+        __typename
+        ... on LabeledEvent { actor { login } createdAt label { name color description } }
+        ... on UnlabeledEvent { actor { login } createdAt label { name color description } }
+        ... on RenamedTitleEvent { actor { login } createdAt }
+        ... on ReopenedEvent { actor { login } createdAt }
+        ... on ConnectedEvent { actor { login } createdAt }
+        ... on DisconnectedEvent { actor { login } createdAt }
+        ... on CrossReferencedEvent {
+            actor { login }
+            createdAt
+            source {
+                __typename
+                ... on Issue { number state body repository { nameWithOwner } }
+                ... on PullRequest { number state body mergedAt repository { nameWithOwner } }
+            }
+        }
+    }";
+
+// fragment returns the fields fetched for issues or pull requests (`type_name`).
+fn fragment(type_name: &str) -> String {
+    let merged_at = if type_name == "PullRequest" {
+        "mergedAt"
+    } else {
+        ""
+    };
+    format!(
+        "fragment {type_name}Fields on {type_name} {{
+            __typename
+            number
+            title
+            body
+            state
+            createdAt
+            updatedAt
+            closedAt
+            author {{ login }}
+            authorAssociation
+            repository {{ nameWithOwner }}
+            labels(first: {n}) {{ nodes {{ name color description }} }}
+            assignees(first: {n}) {{ nodes {{ login }} }}
+            milestone {{ number title state dueOn }}
+            comments(first: {n}) {{ {COMMENT_PAGE} }}
+            timelineItems(first: {n}, itemTypes: {TIMELINE_ITEM_TYPES}) {{ {TIMELINE_PAGE} }}
+            {merged_at}
+        }}",
+        n = NESTED_PAGE_SIZE,
+    )
+}
+
+// list_query returns the query for a page of open issues or pull requests,
+// `connection` being `issues` or `pullRequests`.
+fn list_query(connection: &str, type_name: &str) -> String {
+    format!(
+        "query($owner: String!, $name: String!, $first: Int!, $cursor: String) {{
+            repository(owner: $owner, name: $name) {{
+                items: {connection}(first: $first, after: $cursor, states: OPEN,
+                        orderBy: {{ field: CREATED_AT, direction: DESC }}) {{
+                    pageInfo {{ hasNextPage endCursor }}
+                    nodes {{ ...{type_name}Fields }}
+                }}
+            }}
+        }}
+        {}",
+        fragment(type_name),
+    )
+}
+
+fn issue_query() -> String {
+    format!(
+        "query($owner: String!, $name: String!, $number: Int!) {{
+            repository(owner: $owner, name: $name) {{
+                issueOrPullRequest(number: $number) {{
+                    ... on Issue {{ ...IssueFields }}
+                    ... on PullRequest {{ ...PullRequestFields }}
+                }}
+            }}
+        }}
+        {}
+        {}",
+        fragment("Issue"),
+        fragment("PullRequest"),
+    )
+}
+
+// nested_query returns the query for a page of a connection of a single issue,
+// e.g. `comments(first: 100, after: $cursor)`.
+fn nested_query(connection: &str, page: &str) -> String {
+    format!(
+        "query($owner: String!, $name: String!, $number: Int!, $cursor: String) {{
+            repository(owner: $owner, name: $name) {{
+                issueOrPullRequest(number: $number) {{
+                    ... on Issue {{ items: {connection} {{ {page} }} }}
+                    ... on PullRequest {{ items: {connection} {{ {page} }} }}
+                }}
+            }}
+        }}"
+    )
+}
+
+#[derive(Deserialize)]
+struct RepositoryData<T> {
+    repository: T,
+}
+
+// ListData is a connection aliased as `items`.
+#[derive(Deserialize)]
+struct ListData<T> {
+    items: Page<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssueData<T> {
+    issue_or_pull_request: Option<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page<T> {
+    #[serde(default)]
+    total_count: u32,
+    #[serde(default)]
+    page_info: PageInfo,
+    nodes: Vec<T>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Repository {
+    name_with_owner: String,
+}
+
+// State is the state of an issue (OPEN, CLOSED) or a pull request (OPEN, CLOSED, MERGED).
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum State {
+    Open,
+    Closed,
+    Merged,
+}
+
+impl From<State> for IssueState {
+    fn from(state: State) -> Self {
+        match state {
+            State::Open => IssueState::Open,
+            State::Closed | State::Merged => IssueState::Closed,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlIssue {
+    #[serde(rename = "__typename")]
+    typename: String,
+    number: u32,
+    title: String,
+    body: Option<String>,
+    state: State,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    closed_at: Option<DateTime<Utc>>,
+    // author is null for deleted accounts
+    author: Option<User>,
+    author_association: AuthorAssociation,
+    repository: Repository,
+    labels: Page<Label>,
+    assignees: Page<User>,
+    milestone: Option<GqlMilestone>,
+    comments: Page<GqlComment>,
+    timeline_items: Page<GqlTimelineItem>,
+    #[serde(default)]
+    merged_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlMilestone {
+    number: u32,
+    title: String,
+    state: State,
+    due_on: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlComment {
+    database_id: u64,
+    author: Option<User>,
+    body: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    author_association: AuthorAssociation,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "__typename", rename_all_fields = "camelCase")]
+enum GqlTimelineItem {
+    LabeledEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+        label: Label,
+    },
+    UnlabeledEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+        label: Label,
+    },
+    RenamedTitleEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+    },
+    ReopenedEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+    },
+    ConnectedEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+    },
+    DisconnectedEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+    },
+    CrossReferencedEvent {
+        actor: Option<User>,
+        created_at: DateTime<Utc>,
+        source: GqlSource,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlSource {
+    #[serde(rename = "__typename")]
+    typename: String,
+    number: u32,
+    state: State,
+    body: Option<String>,
+    repository: Repository,
+    #[serde(default)]
+    merged_at: Option<DateTime<Utc>>,
+}
+
+impl GraphQLClient {
+    pub fn new(rest: Client) -> Self {
+        GraphQLClient {
+            rest,
+            prefetched: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // api_url returns the REST API URL of the issue or pull request, which the
+    // engine uses to tell which repository it belongs to.
+    fn api_url(&self, repo: &str, kind: &str, number: u32) -> String {
+        format!("{}/repos/{}/{}/{}", self.rest.api_url, repo, kind, number)
+    }
+
+    fn variables(&self, extra: serde_json::Value) -> serde_json::Value {
+        let mut variables = serde_json::json!({
+            "owner": self.rest.repo_owner,
+            "name": self.rest.repo_name,
+        });
+        if let (Some(variables), serde_json::Value::Object(extra)) =
+            (variables.as_object_mut(), extra)
+        {
+            variables.extend(extra);
+        }
+        variables
+    }
+
+    // get_open fetches all open issues (`issues`) or pull requests (`pullRequests`).
+    async fn get_open(&self, connection: &str, type_name: &str) -> Result<Vec<Issue>, GitHubError> {
+        let query = list_query(connection, type_name);
+        let first = self.rest.per_page.min(MAX_ISSUES_PER_QUERY);
+        let mut issues = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let variables = self.variables(serde_json::json!({ "first": first, "cursor": cursor }));
+            let data: RepositoryData<ListData<GqlIssue>> =
+                self.rest.graphql(&query, variables).await?;
+            let page = data.repository.items;
+            for issue in page.nodes {
+                issues.push(self.convert_issue(issue));
+            }
+            if !page.page_info.has_next_page {
+                return Ok(issues);
+            }
+            cursor = page.page_info.end_cursor;
+        }
+    }
+
+    // get_nested fetches all pages of a connection of a single issue.
+    async fn get_nested<T: DeserializeOwned>(
+        &self,
+        issue_number: u32,
+        connection: &str,
+        page: &str,
+    ) -> Result<Vec<T>, GitHubError> {
+        let query = nested_query(connection, page);
+        let mut nodes = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let variables =
+                self.variables(serde_json::json!({ "number": issue_number, "cursor": cursor }));
+            let data: RepositoryData<IssueData<ListData<T>>> =
+                self.rest.graphql(&query, variables).await?;
+            let Some(issue) = data.repository.issue_or_pull_request else {
+                return Err(not_found(issue_number));
+            };
+            nodes.extend(issue.items.nodes);
+            if !issue.items.page_info.has_next_page {
+                return Ok(nodes);
+            }
+            cursor = issue.items.page_info.end_cursor;
+        }
+    }
+
+    // convert_issue converts the issue and keeps its complete nested connections.
+    fn convert_issue(&self, issue: GqlIssue) -> Issue {
+        let repo = issue.repository.name_with_owner;
+        let prefetched = Prefetched {
+            comments: (!issue.comments.page_info.has_next_page).then(|| {
+                issue
+                    .comments
+                    .nodes
+                    .into_iter()
+                    .map(convert_comment)
+                    .collect()
+            }),
+            timeline: (!issue.timeline_items.page_info.has_next_page).then(|| {
+                issue
+                    .timeline_items
+                    .nodes
+                    .into_iter()
+                    .map(|item| self.convert_timeline_item(item))
+                    .collect()
+            }),
+        };
+        self.prefetched
+            .lock()
+            .unwrap()
+            .insert(issue.number, prefetched);
+        let pull_request = (issue.typename == "PullRequest").then(|| PullRequestRef {
+            url: self.api_url(&repo, "pulls", issue.number),
+            merged_at: issue.merged_at,
+        });
+        Issue {
+            number: issue.number,
+            title: issue.title,
+            body: issue.body,
+            url: self.api_url(&repo, "issues", issue.number),
+            user: issue.author.unwrap_or_default(),
+            labels: issue.labels.nodes,
+            state: issue.state.into(),
+            created_at: issue.created_at,
+            updated_at: issue.updated_at,
+            closed_at: issue.closed_at,
+            assignees: issue.assignees.nodes,
+            milestone: issue.milestone.map(|milestone| Milestone {
+                number: milestone.number,
+                title: milestone.title,
+                state: milestone.state.into(),
+                due_on: milestone.due_on,
+            }),
+            comments: issue.comments.total_count,
+            author_association: issue.author_association,
+            pull_request,
+        }
+    }
+
+    fn convert_timeline_item(&self, item: GqlTimelineItem) -> TimelineEvent {
+        match item {
+            GqlTimelineItem::LabeledEvent {
+                actor,
+                created_at,
+                label,
+            } => TimelineEvent::Labeled {
+                actor: actor.unwrap_or_default(),
+                created_at,
+                label,
+            },
+            GqlTimelineItem::UnlabeledEvent {
+                actor,
+                created_at,
+                label,
+            } => TimelineEvent::Unlabeled {
+                actor: actor.unwrap_or_default(),
+                created_at,
+                label,
+            },
+            GqlTimelineItem::RenamedTitleEvent { actor, created_at } => TimelineEvent::Renamed {
+                actor: actor.unwrap_or_default(),
+                created_at,
+            },
+            GqlTimelineItem::ReopenedEvent { actor, created_at } => TimelineEvent::Reopened {
+                actor: actor.unwrap_or_default(),
+                created_at,
+            },
+            GqlTimelineItem::ConnectedEvent { actor, created_at } => TimelineEvent::Connected {
+                actor: actor.unwrap_or_default(),
+                created_at,
+            },
+            GqlTimelineItem::DisconnectedEvent { actor, created_at } => {
+                TimelineEvent::Disconnected {
+                    actor: actor.unwrap_or_default(),
+                    created_at,
+                }
+            }
+            GqlTimelineItem::CrossReferencedEvent {
+                actor,
+                created_at,
+                source,
+            } => {
+                let repo = source.repository.name_with_owner;
+                let pull_request = (source.typename == "PullRequest").then(|| PullRequestRef {
+                    url: self.api_url(&repo, "pulls", source.number),
+                    merged_at: source.merged_at,
+                });
+                TimelineEvent::CrossReferenced {
+                    actor: actor.unwrap_or_default(),
+                    created_at,
+                    source: CrossReferenceSource {
+                        issue: LinkedIssue {
+                            number: source.number,
+                            url: self.api_url(&repo, "issues", source.number),
+                            state: source.state.into(),
+                            body: source.body,
+                            pull_request,
+                        },
+                    },
+                }
+            }
+            GqlTimelineItem::Other => TimelineEvent::Other,
+        }
+    }
+}
+
+fn convert_comment(comment: GqlComment) -> Comment {
+    Comment {
+        id: comment.database_id,
+        user: comment.author.unwrap_or_default(),
+        body: comment.body,
+        created_at: comment.created_at,
+        updated_at: comment.updated_at,
+        author_association: comment.author_association,
+    }
+}
+
+fn not_found(issue_number: u32) -> GitHubError {
+    GitHubError::NotFound {
+        status: 404,
+        message: format!("issue #{} not found", issue_number),
+    }
+}
+
+#[async_trait::async_trait]
+impl GitHub for GraphQLClient {
+    async fn get_open_issues(
+        &self,
+        include_pull_requests: bool,
+    ) -> Result<Vec<Issue>, GitHubError> {
+        let mut issues = self.get_open("issues", "Issue").await?;
+        if include_pull_requests {
+            issues.extend(self.get_open("pullRequests", "PullRequest").await?);
+            // the REST API lists issues and pull requests together, newest first
+            issues.sort_by_key(|issue| std::cmp::Reverse(issue.created_at));
+        }
+        Ok(issues)
+    }
+
+    async fn get_issue(&self, issue_number: u32) -> Result<Issue, GitHubError> {
+        let variables = self.variables(serde_json::json!({ "number": issue_number }));
+        let data: RepositoryData<IssueData<GqlIssue>> =
+            self.rest.graphql(&issue_query(), variables).await?;
+        let issue = data
+            .repository
+            .issue_or_pull_request
+            .ok_or_else(|| not_found(issue_number))?;
+        Ok(self.convert_issue(issue))
+    }
+
+    async fn get_issue_timeline(
+        &self,
+        issue_number: u32,
+    ) -> Result<Vec<TimelineEvent>, GitHubError> {
+        let prefetched = self
+            .prefetched
+            .lock()
+            .unwrap()
+            .get_mut(&issue_number)
+            .and_then(|prefetched| prefetched.timeline.take());
+        if let Some(timeline) = prefetched {
+            return Ok(timeline);
+        }
+        let connection = format!(
+            "timelineItems(first: {}, after: $cursor, itemTypes: {})",
+            NESTED_PAGE_SIZE, TIMELINE_ITEM_TYPES
+        );
+        let items: Vec<GqlTimelineItem> = self
+            .get_nested(issue_number, &connection, TIMELINE_PAGE)
+            .await?;
+        Ok(items
+            .into_iter()
+            .map(|item| self.convert_timeline_item(item))
+            .collect())
+    }
+
+    async fn get_issue_comments(&self, issue_number: u32) -> Result<Vec<Comment>, GitHubError> {
+        let prefetched = self
+            .prefetched
+            .lock()
+            .unwrap()
+            .get_mut(&issue_number)
+            .and_then(|prefetched| prefetched.comments.take());
+        if let Some(comments) = prefetched {
+            return Ok(comments);
+        }
+        let connection = format!("comments(first: {}, after: $cursor)", NESTED_PAGE_SIZE);
+        let comments: Vec<GqlComment> = self
+            .get_nested(issue_number, &connection, COMMENT_PAGE)
+            .await?;
+        Ok(comments.into_iter().map(convert_comment).collect())
+    }
+
+    async fn get_collaborator_permission(&self, login: &str) -> Result<Permission, GitHubError> {
+        self.rest.get_collaborator_permission(login).await
+    }
+
+    async fn add_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError> {
+        self.rest.add_labels(issue_number, labels).await
+    }
+
+    async fn remove_label(&self, issue_number: u32, label: &str) -> Result<(), GitHubError> {
+        self.rest.remove_label(issue_number, label).await
+    }
+
+    async fn set_labels(&self, issue_number: u32, labels: &[String]) -> Result<(), GitHubError> {
+        self.rest.set_labels(issue_number, labels).await
+    }
+
+    async fn post_comment(&self, issue_number: u32, body: &str) -> Result<(), GitHubError> {
+        self.rest.post_comment(issue_number, body).await
+    }
+
+    async fn close_issue(&self, issue_number: u32) -> Result<(), GitHubError> {
+        self.rest.close_issue(issue_number).await
+    }
+
+    async fn get_file(&self, path: &str) -> Result<Option<RepoFile>, GitHubError> {
+        self.rest.get_file(path).await
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        self.rest.rate_limit.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Auth;
+    use crate::github_client::DEFAULT_PER_PAGE;
+    use crate::rate_limit::RetryPolicy;
+    use chrono::TimeZone;
+    use wiremock::matchers::{body_partial_json, body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(mock_server: &MockServer, per_page: u32) -> GraphQLClient {
+        GraphQLClient::new(Client {
+            api_url: mock_server.uri(),
+            auth: Auth::Token(String::from("secret")),
+            repo_owner: String::from("yurishkuro"),
+            repo_name: String::from("rustybot"),
+            per_page,
+            retry: RetryPolicy::default(),
+            rate_limit: Default::default(),
+            cache: None,
+        })
+    }
+
+    fn issue_json(number: u32, comments_has_next_page: bool) -> serde_json::Value {
+        serde_json::json!({
+            "__typename": "Issue",
+            "number": number,
+            "title": format!("Issue {}", number),
+            "body": "It is broken",
+            "state": "OPEN",
+            "createdAt": format!("2024-07-{:02}T00:00:00Z", number),
+            "updatedAt": "2024-07-20T00:00:00Z",
+            "closedAt": null,
+            "author": { "login": "octocat" },
+            "authorAssociation": "NONE",
+            "repository": { "nameWithOwner": "yurishkuro/rustybot" },
+            "labels": { "nodes": [{ "name": "needs-info", "color": "ededed", "description": null }] },
+            "assignees": { "nodes": [] },
+            "milestone": { "number": 1, "title": "v1", "state": "OPEN", "dueOn": null },
+            "comments": {
+                "totalCount": 2,
+                "pageInfo": { "hasNextPage": comments_has_next_page, "endCursor": "comment1" },
+                "nodes": [{
+                    "databaseId": 1001,
+                    "author": null,
+                    "body": "/in-progress",
+                    "createdAt": "2024-07-15T00:00:00Z",
+                    "updatedAt": "2024-07-15T00:00:00Z",
+                    "authorAssociation": "MEMBER"
+                }]
+            },
+            "timelineItems": {
+                "pageInfo": { "hasNextPage": false, "endCursor": null },
+                "nodes": [
+                    {
+                        "__typename": "LabeledEvent",
+                        "actor": { "login": "yurishkuro" },
+                        "createdAt": "2024-07-10T00:00:00Z",
+                        "label": { "name": "needs-info", "color": "ededed", "description": null }
+                    },
+                    {
+                        "__typename": "CrossReferencedEvent",
+                        "actor": { "login": "octocat" },
+                        "createdAt": "2024-07-12T00:00:00Z",
+                        "source": {
+                            "__typename": "PullRequest",
+                            "number": 13,
+                            "state": "MERGED",
+                            "body": "Fixes #12",
+                            "mergedAt": "2024-07-13T00:00:00Z",
+                            "repository": { "nameWithOwner": "octocat/rustybot" }
+                        }
+                    },
+                    { "__typename": "ClosedEvent" }
+                ]
+            }
+        })
+    }
+
+    fn page(nodes: serde_json::Value, end_cursor: Option<&str>) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "data": {
+                "repository": {
+                    "items": {
+                        "pageInfo": { "hasNextPage": end_cursor.is_some(), "endCursor": end_cursor },
+                        "nodes": nodes
+                    }
+                }
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_get_open_issues() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_string_contains("items: issues("))
+            .and(body_partial_json(serde_json::json!({
+                "variables": { "owner": "yurishkuro", "name": "rustybot", "first": 1, "cursor": null }
+            })))
+            .respond_with(page(serde_json::json!([issue_json(12, false)]), Some("issue12")))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_string_contains("items: issues("))
+            .and(body_partial_json(
+                serde_json::json!({ "variables": { "cursor": "issue12" } }),
+            ))
+            .respond_with(page(serde_json::json!([issue_json(11, true)]), None))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // the comments of #11 did not fit in the issue query
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_string_contains(
+                "items: comments(first: 100, after: $cursor)",
+            ))
+            .and(body_partial_json(serde_json::json!({
+                "variables": { "number": 11, "cursor": null }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "repository": { "issueOrPullRequest": { "items": {
+                    "pageInfo": { "hasNextPage": false, "endCursor": null },
+                    "nodes": [
+                        {
+                            "databaseId": 1001,
+                            "author": { "login": "yurishkuro" },
+                            "body": "first",
+                            "createdAt": "2024-07-15T00:00:00Z",
+                            "updatedAt": "2024-07-15T00:00:00Z",
+                            "authorAssociation": "OWNER"
+                        },
+                        {
+                            "databaseId": 1002,
+                            "author": { "login": "octocat" },
+                            "body": "second",
+                            "createdAt": "2024-07-16T00:00:00Z",
+                            "updatedAt": "2024-07-16T00:00:00Z",
+                            "authorAssociation": "NONE"
+                        }
+                    ]
+                } } } }
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let gh = client(&mock_server, 1);
+        let issues = gh.get_open_issues(false).await.unwrap();
+        assert_eq!(issues.len(), 2);
+        let issue = &issues[0];
+        assert_eq!(issue.number, 12);
+        assert_eq!(
+            issue.url,
+            format!("{}/repos/yurishkuro/rustybot/issues/12", mock_server.uri())
+        );
+        assert_eq!(issue.user.login, "octocat");
+        assert!(issue.has_label("needs-info"));
+        assert_eq!(issue.state, IssueState::Open);
+        assert_eq!(issue.comments, 2);
+        assert_eq!(issue.milestone.as_ref().unwrap().title, "v1");
+        assert!(!issue.is_pull_request());
+        assert_eq!(
+            issue.created_at,
+            Utc.with_ymd_and_hms(2024, 7, 12, 0, 0, 0).unwrap()
+        );
+
+        // comments and timeline of #12 come from the issue query
+        let comments = gh.get_issue_comments(12).await.unwrap();
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].id, 1001);
+        assert_eq!(comments[0].user.login, "");
+        let timeline = gh.get_issue_timeline(12).await.unwrap();
+        assert_eq!(timeline.len(), 3);
+        assert!(matches!(
+            &timeline[0],
+            TimelineEvent::Labeled { actor, label, .. }
+                if actor.login == "yurishkuro" && label.name == "needs-info"
+        ));
+        let TimelineEvent::CrossReferenced { source, .. } = &timeline[1] else {
+            panic!("expecting cross-referenced event, got {:?}", timeline[1]);
+        };
+        assert_eq!(source.issue.number, 13);
+        assert_eq!(source.issue.state, IssueState::Closed);
+        assert_eq!(
+            source.issue.url,
+            format!("{}/repos/octocat/rustybot/issues/13", mock_server.uri())
+        );
+        assert!(source
+            .issue
+            .pull_request
+            .as_ref()
+            .unwrap()
+            .merged_at
+            .is_some());
+        assert!(matches!(timeline[2], TimelineEvent::Other));
+
+        // comments of #11 are fetched separately
+        let comments = gh.get_issue_comments(11).await.unwrap();
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[1].body, "second");
+    }
+
+    #[tokio::test]
+    async fn test_get_open_issues_with_pull_requests() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_string_contains("items: issues("))
+            .respond_with(page(serde_json::json!([issue_json(11, false)]), None))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let mut pull_request = issue_json(12, false);
+        pull_request["__typename"] = "PullRequest".into();
+        pull_request["mergedAt"] = serde_json::Value::Null;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_string_contains("items: pullRequests("))
+            .and(body_string_contains(
+                "fragment PullRequestFields on PullRequest",
+            ))
+            .respond_with(page(serde_json::json!([pull_request]), None))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let issues = client(&mock_server, DEFAULT_PER_PAGE)
+            .get_open_issues(true)
+            .await
+            .unwrap();
+        // newest first
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].number, 12);
+        assert_eq!(
+            issues[0].pull_request.as_ref().unwrap().url,
+            format!("{}/repos/yurishkuro/rustybot/pulls/12", mock_server.uri())
+        );
+        assert_eq!(issues[1].number, 11);
+    }
+
+    #[tokio::test]
+    async fn test_get_issue() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_partial_json(
+                serde_json::json!({ "variables": { "number": 12 } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "repository": { "issueOrPullRequest": issue_json(12, false) } }
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_partial_json(
+                serde_json::json!({ "variables": { "number": 404 } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "repository": { "issueOrPullRequest": null } }
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/graphql"))
+            .and(body_partial_json(serde_json::json!({ "variables": { "number": 500 } })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": null,
+                "errors": [{ "type": "FORBIDDEN", "message": "Resource not accessible by integration" }]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let gh = client(&mock_server, DEFAULT_PER_PAGE);
+        let issue = gh.get_issue(12).await.unwrap();
+        assert_eq!(issue.title, "Issue 12");
+        assert_eq!(gh.get_issue_timeline(12).await.unwrap().len(), 3);
+
+        let err = gh.get_issue(404).await.unwrap_err();
+        assert!(matches!(err, GitHubError::NotFound { .. }), "{}", err);
+        let err = gh.get_issue(500).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "GraphQL errors: Resource not accessible by integration"
+        );
+    }
+}
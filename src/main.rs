@@ -1,49 +1,417 @@
 use clap::Parser;
 use std::env;
 
+mod auth;
+mod cli;
+mod command;
 mod config;
+mod config_extends;
 mod config_loader;
+mod config_source;
+mod config_validator;
+mod engine;
+mod event;
+mod executor;
+#[cfg(test)]
+mod fake_github;
 mod github;
 mod github_client;
+mod github_error;
+mod graphql_client;
+mod rate_limit;
+mod references;
+mod remote_config;
+mod response_cache;
+mod runner;
+mod server;
 
-#[derive(Parser, Debug)]
-#[command(version, about = "rustybot", long_about = None)]
-struct Args {
-    /// Name of the person to greet
-    #[clap(short, long, default_value = "World")]
-    name: String,
-
-    /// Number of times to greet
-    #[clap(short, long, default_value_t = 1)]
-    count: u8,
-}
+use cli::{Args, Command, Repo, RunOptions};
+use config::{Backend, StateMachine};
+use github::GitHub;
+use remote_config::RemoteConfig;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    for _ in 0..args.count {
-        println!("Hello {}!", args.name);
-    }
-    let token = env::var("GITHUB_TOKEN").unwrap_or_default();
-    let api_url =
-        env::var("GITHUB_API_URL").unwrap_or_else(|_| String::from("https://api.github.com"));
-    let gh_client = github_client::Client {
-        api_url,
-        token,
-        repo_owner: String::from("yurishkuro"),
-        repo_name: String::from("rustybot"),
-    };
-    use github::GitHub;
-    match gh_client.get_open_issues().await {
-        Ok(issues) => {
-            for issue in issues {
-                println!(
-                    "#{} - {} - by {}",
-                    issue.number, issue.title, issue.user.login
-                );
+    let result = match args.command {
+        Command::Run {
+            dry_run,
+            ref options,
+        } => run(&args, options, dry_run).await,
+        Command::Plan { ref options } => run(&args, options, true).await,
+        Command::Validate => validate(&args).await,
+        Command::Explain { issue } => explain(&args, issue).await,
+        Command::Serve {
+            ref listen,
+            ref webhook_secret,
+            dry_run,
+        } => serve(&args, listen, webhook_secret, dry_run).await,
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn new_auth(args: &Args) -> Result<auth::Auth, Box<dyn std::error::Error>> {
+    let (Some(app_id), Some(key_path)) = (&args.app_id, &args.app_private_key) else {
+        return Ok(auth::Auth::Token(
+            env::var("GITHUB_TOKEN").unwrap_or_default(),
+        ));
+    };
+    let key = std::fs::read(key_path)
+        .map_err(|err| format!("cannot read GitHub App private key {}: {}", key_path, err))?;
+    let app = auth::AppAuth::new(app_id, &key, args.app_installation_id)?;
+    Ok(auth::Auth::App(std::sync::Arc::new(app)))
+}
+
+fn target_repo(args: &Args) -> Result<&Repo, Box<dyn std::error::Error>> {
+    Ok(args
+        .repo
+        .as_ref()
+        .ok_or("repository is not specified, use --repo or GITHUB_REPOSITORY")?)
+}
+
+fn repo_client(
+    args: &Args,
+    auth: auth::Auth,
+    limiters: &rate_limit::RateLimiters,
+    repo: &Repo,
+    backend: Backend,
+) -> Box<dyn GitHub> {
+    let rate_limit = limiters.get(&auth, &repo.owner);
+    let rest = github_client::Client {
+        api_url: args.api_url.clone(),
+        auth,
+        repo_owner: repo.owner.clone(),
+        repo_name: repo.name.clone(),
+        per_page: github_client::DEFAULT_PER_PAGE,
+        retry: rate_limit::RetryPolicy {
+            max_wait: std::time::Duration::from_secs(args.max_rate_limit_wait),
+            ..Default::default()
+        },
+        rate_limit,
+        cache: args
+            .cache_dir()
+            .map(|dir| response_cache::ResponseCache { dir: dir.into() }),
+    };
+    match backend {
+        Backend::Rest => Box::new(rest),
+        Backend::Graphql => Box::new(graphql_client::GraphQLClient::new(rest)),
+    }
+}
+
+// repo_clients creates the clients of the repositories that configurations are read from.
+fn repo_clients<'a>(
+    args: &'a Args,
+    auth: &'a auth::Auth,
+    limiters: &'a rate_limit::RateLimiters,
+) -> impl Fn(&Repo) -> Arc<dyn GitHub> + 'a {
+    // configuration files are always read with the REST API
+    |repo| {
+        Arc::from(repo_client(
+            args,
+            auth.clone(),
+            limiters,
+            repo,
+            Backend::Rest,
+        ))
+    }
+}
+
+// backend returns the API used to read issues, set with --backend or in the configuration.
+fn backend(args: &Args, config: &StateMachine) -> Backend {
+    args.backend.or(config.backend).unwrap_or(Backend::Rest)
+}
+
+async fn run(
+    args: &Args,
+    options: &RunOptions,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = target_repo(args)?;
+    let auth = new_auth(args)?;
+    let limiters = rate_limit::RateLimiters::default();
+    let remote = args.repo_config.as_deref().map(RemoteConfig::new);
+    let config = load_config(args, remote.as_ref(), &auth, &limiters, repo).await?;
+    let backend = backend(args, &config);
+    let gh_client = repo_client(args, auth.clone(), &limiters, repo, backend);
+    let event_issue = match (&options.event_name, &options.event_path) {
+        (Some(name), Some(path)) => event::read_issue_number(name, path)?,
+        _ => None,
+    };
+    let mut issues = match event_issue {
+        Some(number) => {
+            println!(
+                "Evaluating #{} affected by {} event",
+                number,
+                options.event_name.as_deref().unwrap_or_default()
+            );
+            let issue = gh_client.get_issue(number).await?;
+            // closed issues are not managed
+            if issue.state == github::IssueState::Open {
+                vec![issue]
+            } else {
+                vec![]
+            }
+        }
+        None => {
+            gh_client
+                .get_open_issues(config.pull_requests.is_some())
+                .await?
+        }
+    };
+    if options.max_issues > 0 {
+        issues.truncate(options.max_issues);
+    }
+    let now = chrono::Utc::now();
+    let total = issues.len();
+    let mut failed = 0;
+    for (i, issue) in issues.iter().enumerate() {
+        let result = runner::process_issue(
+            gh_client.as_ref(),
+            &config,
+            issue,
+            now,
+            &args.bot_login,
+            dry_run,
+        )
+        .await;
+        let Err(err) = result else {
+            continue;
+        };
+        failed += 1;
+        if err.recovery() == github_error::Recovery::Abort {
+            println!("Stopping: {}, {} issues not evaluated", err, total - i - 1);
+            if let Some(limit) = gh_client.rate_limit() {
+                println!("GitHub API rate limit: {}", limit);
+            }
+            // running out of rate limit is expected when sweeping large repositories
+            return match err {
+                github_error::GitHubError::RateLimited { .. } => Ok(()),
+                err => Err(err.into()),
+            };
+        }
+    }
+    if failed > 0 {
+        println!("{} of {} issues failed", failed, total);
+    }
+    if let Some(limit) = gh_client.rate_limit() {
+        println!("GitHub API rate limit: {}", limit);
+    }
+    Ok(())
+}
+
+async fn validate(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    // inside GitHub Actions, problems are also printed as workflow commands,
+    // which show them inline on the pull request that changed the config
+    let annotate = env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true");
+    let auth = new_auth(args)?;
+    let limiters = rate_limit::RateLimiters::default();
+    let repos = repo_clients(args, &auth, &limiters);
+    let (config, warnings) = match config_loader::check_config(&args.config, &repos).await {
+        Ok(result) => result,
+        Err(err) => {
+            let config_err = err.downcast_ref::<config_loader::ConfigError>();
+            for diagnostic in config_err.map(|err| err.diagnostics()).unwrap_or_default() {
+                if annotate {
+                    println!("{}", diagnostic.workflow_command());
+                }
             }
+            return Err(err);
+        }
+    };
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+        if annotate {
+            println!("{}", warning.workflow_command());
+        }
+    }
+    println!(
+        "{}: valid configuration with {} states",
+        args.config,
+        config.states.len()
+    );
+    Ok(())
+}
+
+async fn explain(args: &Args, issue_number: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = target_repo(args)?;
+    let auth = new_auth(args)?;
+    let limiters = rate_limit::RateLimiters::default();
+    let remote = args.repo_config.as_deref().map(RemoteConfig::new);
+    let config = load_config(args, remote.as_ref(), &auth, &limiters, repo).await?;
+    let backend = backend(args, &config);
+    let gh_client = repo_client(args, auth.clone(), &limiters, repo, backend);
+    let issue = gh_client.get_issue(issue_number).await?;
+    println!(
+        "#{} - {} - by {}",
+        issue.number, issue.title, issue.user.login
+    );
+    if issue.is_pull_request() {
+        println!("  pull request");
+    }
+    let labels: Vec<&str> = issue.labels.iter().map(|l| l.name.as_str()).collect();
+    println!("  labels: {}", labels.join(", "));
+    println!(
+        "  created: {}, updated: {}, comments: {}",
+        issue.created_at, issue.updated_at, issue.comments
+    );
+    let Some(state) = engine::find_state(&config, &issue) else {
+        println!("  state: none, the issue has none of the state labels");
+        return Ok(());
+    };
+    let ctx = engine::Context::load(
+        gh_client.as_ref(),
+        &config,
+        &issue,
+        chrono::Utc::now(),
+        &args.bot_login,
+    )
+    .await?;
+    println!(
+        "  state: {} - {} (since {})",
+        state.label,
+        state.description,
+        ctx.state_entered_at(state)
+    );
+    if let Some(at) = ctx.last_activity() {
+        println!("  last activity: {}", at);
+    }
+    let mut fired = false;
+    for transition in &state.transitions {
+        let applies = engine::transition_applies(&ctx, state, transition);
+        let verdict = match (applies, fired) {
+            (true, false) => "fires",
+            (true, true) => "applies, but an earlier transition fires",
+            (false, _) if engine::already_applied(&ctx, state, transition) => "already applied",
+            (false, _) => "does not apply",
+        };
+        fired |= applies;
+        println!("  transition: {} ({})", transition.description, verdict);
+        for condition in &transition.conditions {
+            let mark = if engine::condition_holds(&ctx, state, transition, condition) {
+                "yes"
+            } else {
+                "no"
+            };
+            match condition {
+                config::Condition::Timeout(_) => println!(
+                    "    condition: {} since {} - {}",
+                    condition,
+                    ctx.timeout_start(state, transition),
+                    mark
+                ),
+                _ => println!("    condition: {} - {}", condition, mark),
+            }
+        }
+        for action in &transition.actions {
+            println!("    action: {}", action);
+        }
+    }
+    Ok(())
+}
+
+// load_config returns the configuration for the repository, read from the repository
+// itself with --repo-config, otherwise from the local --config file.
+async fn load_config(
+    args: &Args,
+    remote: Option<&RemoteConfig>,
+    auth: &auth::Auth,
+    limiters: &rate_limit::RateLimiters,
+    repo: &Repo,
+) -> Result<Arc<StateMachine>, Box<dyn std::error::Error>> {
+    let repos = repo_clients(args, auth, limiters);
+    match remote {
+        Some(remote) => remote.load(repo, &repos).await,
+        None => Ok(Arc::new(
+            config_loader::load_config(&args.config, &repos).await?,
+        )),
+    }
+}
+
+// Capacity of the queue of webhook events waiting to be evaluated.
+const SERVE_QUEUE_SIZE: usize = 1000;
+
+async fn serve(
+    args: &Args,
+    listen: &str,
+    webhook_secret: &str,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // with --repo-config, the configuration of each repository is loaded for its events
+    let remote = args.repo_config.as_deref().map(RemoteConfig::new);
+    // shared by all repositories, so app installation tokens are cached across events
+    let auth = new_auth(args)?;
+    // likewise, so requests wait for exhausted rate limits across events
+    let limiters = rate_limit::RateLimiters::default();
+    let local_config = match &remote {
+        Some(_) => None,
+        None => {
+            let repos = repo_clients(args, &auth, &limiters);
+            Some(Arc::new(
+                config_loader::load_config(&args.config, &repos).await?,
+            ))
         }
-        Err(err) => eprintln!("Error: {}", err),
+    };
+    let (queue, mut jobs) = tokio::sync::mpsc::channel(SERVE_QUEUE_SIZE);
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    println!("Listening for webhooks on http://{}/webhook", listen);
+    let server = axum::serve(listener, server::router(webhook_secret, queue));
+    // the worker runs on this task, evaluating one issue at a time
+    let worker = async {
+        while let Some(job) = jobs.recv().await {
+            println!(
+                "Evaluating {}#{} affected by {} event",
+                job.repo, job.issue_number, job.event_name
+            );
+            let config = match &local_config {
+                Some(config) => config.clone(),
+                None => match load_config(args, remote.as_ref(), &auth, &limiters, &job.repo).await
+                {
+                    Ok(config) => config,
+                    Err(err) => {
+                        eprintln!("  error: {}", err);
+                        continue;
+                    }
+                },
+            };
+            let backend = backend(args, &config);
+            let gh_client = repo_client(args, auth.clone(), &limiters, &job.repo, backend);
+            // errors are already reported, and the next event may succeed
+            let _ =
+                process_event_issue(gh_client.as_ref(), args, &config, job.issue_number, dry_run)
+                    .await;
+        }
+    };
+    tokio::select! {
+        result = server => result?,
+        _ = worker => {},
+    }
+    Ok(())
+}
+
+// process_event_issue evaluates the issue affected by a webhook event, unless it is closed.
+// Errors are printed and returned, like in runner::process_issue.
+async fn process_event_issue(
+    gh_client: &dyn GitHub,
+    args: &Args,
+    config: &config::StateMachine,
+    issue_number: u32,
+    dry_run: bool,
+) -> Result<(), github_error::GitHubError> {
+    let issue = match gh_client.get_issue(issue_number).await {
+        Ok(issue) => issue,
+        Err(err) => {
+            eprintln!("  error: {}", err);
+            return Err(err);
+        }
+    };
+    // closed issues are not managed
+    if issue.state != github::IssueState::Open {
+        return Ok(());
     }
-    config_loader::load_config("issue-manager.yml").expect("Expecting valid config");
+    let now = chrono::Utc::now();
+    runner::process_issue(gh_client, config, &issue, now, &args.bot_login, dry_run).await
 }
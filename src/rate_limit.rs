@@ -0,0 +1,324 @@
+use super::auth::Auth;
+use super::github_error::GitHubError;
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// GitHub limits the number of requests per hour (the primary rate limit) and
+// rejects bursts of requests (secondary rate limits) with 403 or 429 responses.
+// Every response reports the primary limit in the X-RateLimit-* headers, and
+// secondary limit responses may ask the client to wait with Retry-After.
+// See https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api
+
+// RetryPolicy controls how failed requests are retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    // number of retries of a failed request
+    pub max_retries: u32,
+    // delay before the first retry of a transient error, doubled for every next retry
+    pub initial_backoff: Duration,
+    // longest wait for a rate limit to reset, the request fails if it would take longer
+    pub max_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_wait: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // backoff returns the delay before the retry of a transient error.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff * 2u32.saturating_pow(attempt)
+    }
+
+    // retry_delay returns how long to wait before retrying a request that failed
+    // with the response status and headers, or None if it should not be retried.
+    // Rate limited requests were rejected before being processed and are always
+    // retried, but server errors only for idempotent requests, since GitHub may
+    // have applied the request anyway, e.g. posted a comment.
+    pub fn retry_delay(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+        attempt: u32,
+        idempotent: bool,
+    ) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        match status {
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+                if let Some(delay) = retry_after(headers) {
+                    return (delay <= self.max_wait).then_some(delay);
+                }
+                if RateLimit::from_headers(headers).is_some_and(|limit| limit.remaining == 0) {
+                    // waiting for the reset is up to the caller, see RateLimiter::wait
+                    return Some(Duration::ZERO);
+                }
+                // secondary rate limit without Retry-After: wait at least a minute
+                let secondary = body.to_lowercase().contains("secondary rate limit");
+                let delay = Duration::from_secs(60);
+                (secondary && delay <= self.max_wait).then_some(delay)
+            }
+            StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+                if idempotent =>
+            {
+                Some(self.backoff(attempt))
+            }
+            _ => None,
+        }
+    }
+}
+
+// is_idempotent returns whether sending the request again after a failure
+// cannot apply it twice.
+pub fn is_idempotent(method: &reqwest::Method) -> bool {
+    use reqwest::Method;
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+    )
+}
+
+// retry_after parses the Retry-After header given in seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+// RateLimit is the state of the primary rate limit reported by the last response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        let header =
+            |name: &str| -> Option<i64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+        Some(RateLimit {
+            limit: header("X-RateLimit-Limit")?.try_into().ok()?,
+            remaining: header("X-RateLimit-Remaining")?.try_into().ok()?,
+            reset: Utc
+                .timestamp_opt(header("X-RateLimit-Reset")?, 0)
+                .single()?,
+        })
+    }
+}
+
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} requests remaining, resets at {}",
+            self.remaining, self.limit, self.reset
+        )
+    }
+}
+
+// RateLimiter tracks the primary rate limit across the requests of a client.
+#[derive(Default)]
+pub struct RateLimiter {
+    last: Mutex<Option<RateLimit>>,
+}
+
+impl RateLimiter {
+    pub fn update(&self, headers: &HeaderMap) {
+        if let Some(limit) = RateLimit::from_headers(headers) {
+            *self.last.lock().unwrap() = Some(limit);
+        }
+    }
+
+    // get returns the rate limit reported by the last response, if any.
+    pub fn get(&self) -> Option<RateLimit> {
+        self.last.lock().unwrap().clone()
+    }
+
+    // exceeded returns the time the rate limit resets if it is exhausted
+    // and the reset is later than the policy allows to wait.
+    pub fn exceeded(&self, policy: &RetryPolicy, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let limit = self.get()?;
+        let wait = (limit.reset - now).to_std().ok()?;
+        (limit.remaining == 0 && wait > policy.max_wait).then_some(limit.reset)
+    }
+
+    // wait sleeps until the rate limit resets if it is exhausted,
+    // or fails with GitHubError::RateLimited if that would take too long.
+    pub async fn wait(&self, policy: &RetryPolicy) -> Result<(), GitHubError> {
+        let now = Utc::now();
+        if let Some(reset) = self.exceeded(policy, now) {
+            return Err(GitHubError::RateLimited { reset: Some(reset) });
+        }
+        let Some(limit) = self.get().filter(|limit| limit.remaining == 0) else {
+            return Ok(());
+        };
+        if let Ok(wait) = (limit.reset - now).to_std() {
+            eprintln!("Rate limit exhausted, waiting until {}", limit.reset);
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+}
+
+// RateLimiters shares the rate limit of a token between the clients of the
+// repositories it is used for, e.g. by the webhook server, which creates clients
+// for every event. A personal access token has a single rate limit, while the
+// installation tokens of a GitHub App have one per owner.
+#[derive(Default)]
+pub struct RateLimiters {
+    limiters: Mutex<HashMap<String, Arc<RateLimiter>>>,
+}
+
+impl RateLimiters {
+    // get returns the limiter of the requests to the repositories of the owner.
+    pub fn get(&self, auth: &Auth, owner: &str) -> Arc<RateLimiter> {
+        let key = match auth {
+            Auth::Token(_) => "",
+            Auth::App(_) => owner,
+        };
+        let mut limiters = self.limiters.lock().unwrap();
+        limiters.entry(key.to_string()).or_default().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_rate_limit_from_headers() {
+        let headers = headers(&[
+            ("X-RateLimit-Limit", "5000"),
+            ("X-RateLimit-Remaining", "4999"),
+            ("X-RateLimit-Reset", "1372700873"),
+        ]);
+        let limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(limit.limit, 5000);
+        assert_eq!(limit.remaining, 4999);
+        assert_eq!(limit.reset, Utc.timestamp_opt(1372700873, 0).unwrap());
+        assert_eq!(
+            limit.to_string(),
+            "4999 of 5000 requests remaining, resets at 2013-07-01 17:47:53 UTC"
+        );
+        assert_eq!(RateLimit::from_headers(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::default();
+        let none = HeaderMap::new();
+        assert_eq!(
+            policy.retry_delay(StatusCode::BAD_GATEWAY, &none, "", 0, true),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.retry_delay(StatusCode::SERVICE_UNAVAILABLE, &none, "", 2, true),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(
+            policy.retry_delay(StatusCode::BAD_GATEWAY, &none, "", 3, true),
+            None
+        );
+        // a POST may have been applied before the server error
+        assert_eq!(
+            policy.retry_delay(StatusCode::BAD_GATEWAY, &none, "", 0, false),
+            None
+        );
+        assert_eq!(
+            policy.retry_delay(StatusCode::NOT_FOUND, &none, "", 0, true),
+            None
+        );
+        // permission denied, not a rate limit
+        assert_eq!(
+            policy.retry_delay(StatusCode::FORBIDDEN, &none, "", 0, true),
+            None
+        );
+
+        let retry_after = headers(&[("Retry-After", "30")]);
+        assert_eq!(
+            policy.retry_delay(StatusCode::TOO_MANY_REQUESTS, &retry_after, "", 0, false),
+            Some(Duration::from_secs(30))
+        );
+        let retry_after = headers(&[("Retry-After", "3600")]);
+        assert_eq!(
+            policy.retry_delay(StatusCode::FORBIDDEN, &retry_after, "", 0, true),
+            None
+        );
+        let body = r#"{"message": "You have exceeded a secondary rate limit."}"#;
+        assert_eq!(
+            policy.retry_delay(StatusCode::FORBIDDEN, &none, body, 0, true),
+            Some(Duration::from_secs(60))
+        );
+        let exhausted = headers(&[
+            ("X-RateLimit-Limit", "5000"),
+            ("X-RateLimit-Remaining", "0"),
+            ("X-RateLimit-Reset", "1372700873"),
+        ]);
+        assert_eq!(
+            policy.retry_delay(StatusCode::FORBIDDEN, &exhausted, "", 0, true),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_exceeded() {
+        let policy = RetryPolicy::default();
+        let now = Utc::now();
+        let limiter = RateLimiter::default();
+        assert_eq!(limiter.exceeded(&policy, now), None);
+
+        let reset = now.timestamp() + 3600;
+        limiter.update(&headers(&[
+            ("X-RateLimit-Limit", "5000"),
+            ("X-RateLimit-Remaining", "0"),
+            ("X-RateLimit-Reset", &reset.to_string()),
+        ]));
+        assert_eq!(
+            limiter
+                .exceeded(&policy, now)
+                .map(|reset| reset.timestamp()),
+            Some(reset)
+        );
+        // resets soon enough to wait
+        let later = now + chrono::Duration::minutes(58);
+        assert_eq!(limiter.exceeded(&policy, later), None);
+    }
+
+    #[test]
+    fn test_rate_limiters() {
+        let limiters = RateLimiters::default();
+        let auth = Auth::Token("secret".to_string());
+        let limiter = limiters.get(&auth, "yurishkuro");
+        limiter.update(&headers(&[
+            ("X-RateLimit-Limit", "5000"),
+            ("X-RateLimit-Remaining", "10"),
+            ("X-RateLimit-Reset", "1372700873"),
+        ]));
+        // a token has the same rate limit for all owners
+        let other = limiters.get(&auth, "other");
+        assert_eq!(other.get().unwrap().remaining, 10);
+    }
+}
@@ -0,0 +1,126 @@
+// Pull requests close issues when their description uses one of the closing
+// keywords followed by a reference to the issue, e.g. `Fixes #12`,
+// `closes yurishkuro/rustybot#12` or `Resolves https://github.com/yurishkuro/rustybot/issues/12`,
+// also with the host of a GitHub Enterprise Server.
+// See https://docs.github.com/en/issues/tracking-your-work-with-issues/linking-a-pull-request-to-an-issue
+
+const CLOSING_KEYWORDS: &[&str] = &[
+    "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+];
+
+// repo_from_api_url extracts `owner/name` from the API URL of an issue or pull
+// request, e.g. `https://api.github.com/repos/yurishkuro/rustybot/issues/12`.
+pub fn repo_from_api_url(url: &str) -> Option<String> {
+    let (_, path) = url.split_once("/repos/")?;
+    let mut segments = path.split('/');
+    let owner = segments.next().filter(|s| !s.is_empty())?;
+    let name = segments.next().filter(|s| !s.is_empty())?;
+    Some(format!("{}/{}", owner, name))
+}
+
+// closes_issue returns true if the text written in repository `text_repo`
+// uses a closing keyword to reference issue `issue_number` of repository `issue_repo`.
+// Both repositories are in the `owner/name` form.
+pub fn closes_issue(text: &str, text_repo: &str, issue_repo: &str, issue_number: u32) -> bool {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words.windows(2).any(|pair| {
+        let keyword = pair[0].trim_end_matches(':').to_lowercase();
+        CLOSING_KEYWORDS.contains(&keyword.as_str())
+            && parse_reference(pair[1], text_repo).is_some_and(|(repo, number)| {
+                number == issue_number && repo.eq_ignore_ascii_case(issue_repo)
+            })
+    })
+}
+
+// parse_reference parses an issue reference into the repository and issue number.
+// Short references (`#12`) refer to issues in `default_repo`. URLs may have any
+// host, since issues on GitHub Enterprise Server are not on github.com.
+fn parse_reference<'a>(reference: &'a str, default_repo: &'a str) -> Option<(&'a str, u32)> {
+    let reference = reference.trim_end_matches(['.', ',', ';', ')']);
+    if let Some(url) = reference
+        .strip_prefix("https://")
+        .or_else(|| reference.strip_prefix("http://"))
+    {
+        let (_, path) = url.split_once('/').filter(|(host, _)| !host.is_empty())?;
+        let mut segments = path.splitn(4, '/');
+        let owner = segments.next()?;
+        let name = segments.next()?;
+        if segments.next()? != "issues" {
+            return None;
+        }
+        let number = segments.next()?.parse().ok()?;
+        let repo = &path[..owner.len() + 1 + name.len()];
+        return Some((repo, number));
+    }
+    let (repo, number) = reference.split_once('#')?;
+    let repo = if repo.is_empty() { default_repo } else { repo };
+    Some((repo, number.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_from_api_url() {
+        assert_eq!(
+            repo_from_api_url("https://api.github.com/repos/yurishkuro/rustybot/issues/12")
+                .as_deref(),
+            Some("yurishkuro/rustybot")
+        );
+        assert_eq!(
+            repo_from_api_url("https://github.example.com/api/v3/repos/org/repo/pulls/3")
+                .as_deref(),
+            Some("org/repo")
+        );
+        assert_eq!(repo_from_api_url("https://api.github.com/issues/12"), None);
+    }
+
+    #[test]
+    fn test_closes_issue() {
+        let repo = "yurishkuro/rustybot";
+        assert!(closes_issue("Fixes #12", repo, repo, 12));
+        assert!(closes_issue("This PR\n\nresolves: #12.", repo, repo, 12));
+        assert!(closes_issue(
+            "Closes yurishkuro/rustybot#12",
+            "octocat/fork",
+            repo,
+            12
+        ));
+        assert!(closes_issue(
+            "fixed https://github.com/yurishkuro/rustybot/issues/12",
+            "octocat/fork",
+            repo,
+            12
+        ));
+        assert!(closes_issue("Fixes #11, fixes #12", repo, repo, 12));
+        // GitHub Enterprise Server
+        assert!(closes_issue(
+            "Fixes https://github.example.com/yurishkuro/rustybot/issues/12",
+            repo,
+            repo,
+            12
+        ));
+
+        // not a closing keyword
+        assert!(!closes_issue("Related to #12", repo, repo, 12));
+        // different issue
+        assert!(!closes_issue("Fixes #123", repo, repo, 12));
+        // short reference in a pull request from another repository
+        assert!(!closes_issue("Fixes #12", "octocat/fork", repo, 12));
+        // URL without a host
+        assert!(!closes_issue(
+            "Fixes https:///yurishkuro/rustybot/issues/12",
+            repo,
+            repo,
+            12
+        ));
+        // pull request URL
+        assert!(!closes_issue(
+            "Fixes https://github.com/yurishkuro/rustybot/pull/12",
+            repo,
+            repo,
+            12
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+use super::cli::Repo;
+use super::config::StateMachine;
+use super::config_extends::{self, Document, Origin, RepoClients};
+use super::config_loader;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The configuration can be read from the default branch of each managed
+// repository instead of a local file, so that a single bot serving many
+// repositories follows the state machine of each of them. Repositories
+// without the file use the default of their organization, kept at the same
+// path in its `.github` repository, like the community health files.
+// See https://docs.github.com/en/communities/setting-up-your-project-for-healthy-contributions/creating-a-default-community-health-file
+
+// Repository of an organization holding the defaults for its repositories.
+pub const ORG_CONFIG_REPO: &str = ".github";
+
+pub struct RemoteConfig {
+    // path of the configuration file in the repositories, e.g. `.github/issue-manager.yml`
+    pub path: String,
+    // configurations by the SHAs of the blobs of their chain of files, so an unchanged
+    // configuration is only validated once
+    parsed: Mutex<HashMap<String, Arc<StateMachine>>>,
+}
+
+impl RemoteConfig {
+    pub fn new(path: &str) -> Self {
+        RemoteConfig {
+            path: path.to_string(),
+            parsed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // load returns the configuration of the repository, falling back to the one of
+    // its organization, merged with the configurations it extends.
+    pub async fn load(
+        &self,
+        repo: &Repo,
+        repos: RepoClients<'_>,
+    ) -> Result<Arc<StateMachine>, Box<dyn std::error::Error>> {
+        let org = Repo {
+            owner: repo.owner.clone(),
+            name: ORG_CONFIG_REPO.to_string(),
+        };
+        for repo in [repo, &org] {
+            let Some(file) = repos(repo).get_file(&self.path).await? else {
+                continue;
+            };
+            let document = Document {
+                // problems are reported as e.g. `owner/repo:.github/issue-manager.yml:3:5`
+                origin: Origin::Repo {
+                    repo: repo.clone(),
+                    path: self.path.clone(),
+                },
+                content: file.content,
+                sha: Some(file.sha),
+            };
+            return self.parse(document, repos).await;
+        }
+        Err(format!(
+            "configuration {} not found in {} or {}",
+            self.path, repo, org
+        )
+        .into())
+    }
+
+    async fn parse(
+        &self,
+        document: Document,
+        repos: RepoClients<'_>,
+    ) -> Result<Arc<StateMachine>, Box<dyn std::error::Error>> {
+        // the bases are read again each time, since any file of the chain may change
+        let documents = config_extends::resolve(document, repos).await?;
+        let shas: Vec<&str> = documents.iter().filter_map(|d| d.sha.as_deref()).collect();
+        let key = shas.join(",");
+        if let Some(config) = self.parsed.lock().unwrap().get(&key) {
+            return Ok(config.clone());
+        }
+        let config = Arc::new(config_loader::load_documents(&documents)?);
+        self.parsed.lock().unwrap().insert(key, config.clone());
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_github::FakeGitHub;
+    use crate::github::{GitHub, RepoFile};
+
+    const PATH: &str = ".github/issue-manager.yml";
+
+    fn file(path: &str, sha: &str, content: &str) -> RepoFile {
+        RepoFile {
+            path: path.to_string(),
+            sha: sha.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn config_with_state(label: &str) -> String {
+        format!(
+            "states:\n  - description: {}\n    label: {}\n    transitions: []\n",
+            label, label
+        )
+    }
+
+    // clients serves the fake repositories by name.
+    fn clients(repos: &[(&str, &Arc<FakeGitHub>)]) -> impl Fn(&Repo) -> Arc<dyn GitHub> {
+        let repos: HashMap<String, Arc<FakeGitHub>> = repos
+            .iter()
+            .map(|(name, gh)| (name.to_string(), Arc::clone(gh)))
+            .collect();
+        move |repo| match repos.get(&repo.to_string()) {
+            Some(gh) => gh.clone(),
+            None => Arc::new(FakeGitHub::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let repo: Repo = "yurishkuro/rustybot".parse().unwrap();
+        let remote = RemoteConfig::new(PATH);
+        let mut org = FakeGitHub::default();
+        org.files.insert(
+            PATH.to_string(),
+            file(PATH, "org", &config_with_state("org")),
+        );
+        let org = Arc::new(org);
+
+        let repos = clients(&[("yurishkuro/.github", &org)]);
+        let config = remote.load(&repo, &repos).await.unwrap();
+        assert_eq!(config.states[0].label, "org");
+
+        let mut gh = FakeGitHub::default();
+        gh.files.insert(
+            PATH.to_string(),
+            file(PATH, "v1", &config_with_state("repo")),
+        );
+        let gh = Arc::new(gh);
+        let repos = clients(&[("yurishkuro/rustybot", &gh), ("yurishkuro/.github", &org)]);
+        let config = remote.load(&repo, &repos).await.unwrap();
+        assert_eq!(config.states[0].label, "repo");
+        assert_eq!(org.reads("get_file"), 1);
+
+        // an unchanged blob is not parsed again
+        let again = remote.load(&repo, &repos).await.unwrap();
+        assert!(Arc::ptr_eq(&config, &again));
+
+        let mut gh = FakeGitHub::default();
+        gh.files
+            .insert(PATH.to_string(), file(PATH, "v2", "states: 1"));
+        let gh = Arc::new(gh);
+        let repos = clients(&[("yurishkuro/rustybot", &gh), ("yurishkuro/.github", &org)]);
+        let err = remote.load(&repo, &repos).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("yurishkuro/rustybot:.github/issue-manager.yml:1:9"),
+            "{}",
+            err
+        );
+
+        let repos = clients(&[]);
+        let err = remote.load(&repo, &repos).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "configuration .github/issue-manager.yml not found \
+             in yurishkuro/rustybot or yurishkuro/.github"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_extends() {
+        let repo: Repo = "yurishkuro/rustybot".parse().unwrap();
+        let remote = RemoteConfig::new(PATH);
+        let base = |sha| file("base.yml", sha, &config_with_state("base"));
+        let mut org = FakeGitHub::default();
+        org.files.insert("base.yml".to_string(), base("b1"));
+        let org = Arc::new(org);
+        let mut gh = FakeGitHub::default();
+        let content = "extends: yurishkuro/.github:base.yml\n";
+        gh.files.insert(PATH.to_string(), file(PATH, "v1", content));
+        let gh = Arc::new(gh);
+
+        let repos = clients(&[("yurishkuro/rustybot", &gh), ("yurishkuro/.github", &org)]);
+        let config = remote.load(&repo, &repos).await.unwrap();
+        assert_eq!(config.states[0].label, "base");
+        let again = remote.load(&repo, &repos).await.unwrap();
+        assert!(Arc::ptr_eq(&config, &again));
+
+        // a change of the base is picked up even if the extending file is unchanged
+        let mut org = FakeGitHub::default();
+        org.files.insert("base.yml".to_string(), base("b2"));
+        let org = Arc::new(org);
+        let repos = clients(&[("yurishkuro/rustybot", &gh), ("yurishkuro/.github", &org)]);
+        let changed = remote.load(&repo, &repos).await.unwrap();
+        assert!(!Arc::ptr_eq(&config, &changed));
+
+        // paths are relative to the extending file, in the same repository
+        let mut gh = FakeGitHub::default();
+        let content = "extends: ../shared/base.yml\n";
+        gh.files.insert(PATH.to_string(), file(PATH, "v2", content));
+        let base = file("shared/base.yml", "b3", &config_with_state("shared"));
+        gh.files.insert("shared/base.yml".to_string(), base);
+        let gh = Arc::new(gh);
+        let repos = clients(&[("yurishkuro/rustybot", &gh)]);
+        let config = remote.load(&repo, &repos).await.unwrap();
+        assert_eq!(config.states[0].label, "shared");
+    }
+}
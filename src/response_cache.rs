@@ -0,0 +1,139 @@
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use reqwest::header::{LAST_MODIFIED, LINK};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::PathBuf;
+
+// GitHub answers a conditional GET request with 304 Not Modified if the
+// resource has not changed since the ETag or Last-Modified of a previous
+// response, and 304 responses do not count against the rate limit.
+// See https://docs.github.com/en/rest/using-the-rest-api/best-practices-for-using-the-rest-api#use-conditional-requests-if-appropriate
+
+// ResponseCache stores the bodies of GET responses on disk, one file per URL,
+// so that they can be reused across runs, e.g. by saving the directory with actions/cache.
+#[derive(Clone, Debug)]
+pub struct ResponseCache {
+    pub dir: PathBuf,
+}
+
+// Entry is a cached response.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Entry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // the Link header, needed to follow the pages of list endpoints
+    pub link: Option<String>,
+    pub body: String,
+}
+
+impl ResponseCache {
+    // get returns the cached response for the URL. Unreadable entries are treated
+    // as missing, since the response can always be requested again.
+    pub fn get(&self, url: &str) -> Option<Entry> {
+        let data = std::fs::read(self.path(url)).ok()?;
+        let entry: Entry = serde_json::from_slice(&data).ok()?;
+        // guard against hash collisions
+        (entry.url == url).then_some(entry)
+    }
+
+    // put stores the response for the URL if it has an ETag or Last-Modified header,
+    // without which it cannot be validated later.
+    pub fn put(&self, url: &str, headers: &HeaderMap, body: &str) -> std::io::Result<()> {
+        let entry = Entry {
+            url: url.to_string(),
+            etag: header(headers, ETAG),
+            last_modified: header(headers, LAST_MODIFIED),
+            link: header(headers, LINK),
+            body: body.to_string(),
+        };
+        if entry.etag.is_none() && entry.last_modified.is_none() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        // written to a temporary file first, so concurrent readers never see a partial entry
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        file.write_all(&serde_json::to_vec(&entry)?)?;
+        file.persist(self.path(url))?;
+        Ok(())
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let hash = hex::encode(Sha256::digest(url.as_bytes()));
+        self.dir.join(format!("{}.json", hash))
+    }
+}
+
+impl Entry {
+    // conditional_headers returns the headers that make a request for the
+    // entry's URL conditional on the resource having changed.
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let values = [
+            (IF_NONE_MATCH, &self.etag),
+            (IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in values {
+            if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+
+    // headers returns the cached response headers used by the client.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(link) = self
+            .link
+            .as_deref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+        {
+            headers.insert(LINK, link);
+        }
+        headers
+    }
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?;
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache {
+            dir: dir.path().join("responses"),
+        };
+        let url = "https://api.github.com/repos/yurishkuro/rustybot/issues?per_page=100";
+        assert_eq!(cache.get(url), None);
+
+        // responses that cannot be validated are not cached
+        cache.put(url, &HeaderMap::new(), "[]").unwrap();
+        assert_eq!(cache.get(url), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("W/\"abc\""));
+        headers.insert(
+            LINK,
+            HeaderValue::from_static("<https://api.github.com/next>; rel=\"next\""),
+        );
+        cache.put(url, &headers, "[1]").unwrap();
+        let entry = cache.get(url).unwrap();
+        assert_eq!(entry.body, "[1]");
+        assert_eq!(entry.headers().get(LINK), headers.get(LINK));
+        let conditional = entry.conditional_headers();
+        assert_eq!(conditional.get(IF_NONE_MATCH).unwrap(), "W/\"abc\"");
+        assert_eq!(conditional.get(IF_MODIFIED_SINCE), None);
+        assert_eq!(cache.get("https://api.github.com/other"), None);
+
+        std::fs::write(cache.path(url), "not json").unwrap();
+        assert_eq!(cache.get(url), None);
+    }
+}
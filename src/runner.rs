@@ -0,0 +1,233 @@
+use super::config::StateMachine;
+use super::engine;
+use super::executor;
+use super::github::{GitHub, Issue};
+use super::github_error::{GitHubError, Recovery};
+use chrono::{DateTime, Utc};
+
+// process_issue evaluates the state machine against the issue and applies the
+// actions of the transition that fires, or only prints them if dry_run is set.
+// Transient errors are retried once, unless an action was already applied: the
+// retry would start from the stale issue and could apply some actions twice, e.g.
+// post the same comment again. Other errors are printed and returned, so the
+// caller can decide whether to continue with other issues, see
+// GitHubError::recovery.
+pub async fn process_issue(
+    gh: &dyn GitHub,
+    config: &StateMachine,
+    issue: &Issue,
+    now: DateTime<Utc>,
+    bot_login: &str,
+    dry_run: bool,
+) -> Result<(), GitHubError> {
+    println!(
+        "#{} - {} - by {}",
+        issue.number, issue.title, issue.user.login
+    );
+    match process(gh, config, issue, now, bot_login, dry_run).await {
+        Err(failure) if failure.applied == 0 && failure.err.recovery() == Recovery::Retry => {
+            println!("  retrying");
+            let result = process(gh, config, issue, now, bot_login, dry_run).await;
+            result.map_err(|failure| failure.err)
+        }
+        result => result.map_err(|failure| failure.err),
+    }
+}
+
+// Failure is an error of process, with the number of actions applied before it.
+struct Failure {
+    err: GitHubError,
+    applied: usize,
+}
+
+impl From<GitHubError> for Failure {
+    fn from(err: GitHubError) -> Self {
+        Failure { err, applied: 0 }
+    }
+}
+
+async fn process(
+    gh: &dyn GitHub,
+    config: &StateMachine,
+    issue: &Issue,
+    now: DateTime<Utc>,
+    bot_login: &str,
+    dry_run: bool,
+) -> Result<(), Failure> {
+    let ctx = match engine::Context::load(gh, config, issue, now, bot_login).await {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            eprintln!("  error: {}", err);
+            return Err(err.into());
+        }
+    };
+    let Some(plan) = engine::evaluate(config, &ctx) else {
+        match engine::find_state(config, issue) {
+            Some(state) => println!("  state: {}, no transition applies", state.label),
+            None => println!("  state: none"),
+        }
+        return Ok(());
+    };
+    println!("  state: {}", plan.state.label);
+    println!("  transition: {}", plan.transition.description);
+    if dry_run {
+        for action in plan.actions() {
+            println!("    - {}", action);
+        }
+        return Ok(());
+    }
+    let results = executor::execute(gh, config, issue, plan.actions()).await;
+    for (applied, result) in results.into_iter().enumerate() {
+        match result.result {
+            Ok(()) => println!("    - {}: ok", result.action),
+            Err(err) => {
+                // the executor stops at the first failure, so this is the last result
+                eprintln!("    - {}: error: {}", result.action, err);
+                return Err(Failure { err, applied });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_github::{FakeGitHub, Mutation};
+    use crate::github::{Comment, Label, Permission, User};
+
+    #[tokio::test]
+    async fn test_process_issue() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for triage"
+            label: "needs-triage"
+            transitions:
+              - description: "Request more info"
+                conditions:
+                  - type: "command"
+                    command: "needs-info"
+                actions:
+                  - type: "replace-label"
+                    label: "needs-info"
+                  - type: "post-comment"
+                    comment: "Please provide more details."
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions: []
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let issue = Issue {
+            number: 7,
+            labels: vec![
+                Label {
+                    name: "bug".to_string(),
+                    ..Default::default()
+                },
+                Label {
+                    name: "needs-triage".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut gh = FakeGitHub::new(vec![issue.clone()]);
+        gh.comments.insert(
+            7,
+            vec![Comment {
+                user: User {
+                    login: "maintainer".to_string(),
+                },
+                body: "/needs-info".to_string(),
+                created_at: issue.created_at,
+                ..Default::default()
+            }],
+        );
+        gh.permissions
+            .insert("maintainer".to_string(), Permission::Write);
+        let now = Utc::now();
+
+        process_issue(&gh, &config, &issue, now, "bot", true)
+            .await
+            .unwrap();
+        assert!(gh.take_mutations().is_empty());
+
+        process_issue(&gh, &config, &issue, now, "bot", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            gh.take_mutations(),
+            vec![
+                Mutation::SetLabels(7, vec!["bug".to_string(), "needs-info".to_string()]),
+                Mutation::PostComment(7, "Please provide more details.".to_string()),
+            ]
+        );
+
+        // the issue is now in a state without transitions
+        let issue = gh.get_issue(7).await.unwrap();
+        process_issue(&gh, &config, &issue, now, "bot", false)
+            .await
+            .unwrap();
+        assert!(gh.take_mutations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_process_issue_retry() {
+        let yaml = r#"
+        states:
+          - description: "Waiting for info"
+            label: "needs-info"
+            transitions:
+              - description: "Close without info"
+                conditions:
+                  - type: "timeout"
+                    timeout: 30
+                actions:
+                  - type: "post-comment"
+                    comment: "Closing for lack of info."
+                  - type: "close"
+        "#;
+        let config: StateMachine = serde_yaml::from_str(yaml).unwrap();
+        let issue = Issue {
+            number: 7,
+            labels: vec![Label {
+                name: "needs-info".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let now = issue.created_at + chrono::Duration::days(31);
+        let gh = FakeGitHub::new(vec![issue.clone()]);
+
+        // a failure before any action is applied is retried
+        gh.failures.lock().unwrap().push(Mutation::PostComment(
+            7,
+            "Closing for lack of info.".to_string(),
+        ));
+        process_issue(&gh, &config, &issue, now, "bot", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            gh.take_mutations(),
+            vec![
+                Mutation::PostComment(7, "Closing for lack of info.".to_string()),
+                Mutation::CloseIssue(7),
+            ]
+        );
+
+        // the comment is not posted again after a failure to close
+        let gh = FakeGitHub::new(vec![issue.clone()]);
+        gh.failures.lock().unwrap().push(Mutation::CloseIssue(7));
+        let err = process_issue(&gh, &config, &issue, now, "bot", false)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Status: 502 - Bad Gateway");
+        assert_eq!(
+            gh.take_mutations(),
+            vec![Mutation::PostComment(
+                7,
+                "Closing for lack of info.".to_string()
+            )]
+        );
+    }
+}
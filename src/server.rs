@@ -0,0 +1,185 @@
+use super::cli::Repo;
+use super::event;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+// Webhooks are received on POST /webhook, verified against the shared secret
+// and queued; the queue is drained by a single worker that evaluates the
+// affected issue, so the handler can acknowledge deliveries quickly.
+// See https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries
+
+// Job is a queued evaluation of a single issue.
+#[derive(Debug, PartialEq)]
+pub struct Job {
+    pub repo: Repo,
+    pub issue_number: u32,
+    pub event_name: String,
+}
+
+#[derive(Clone)]
+struct AppState {
+    secret: Vec<u8>,
+    queue: mpsc::Sender<Job>,
+}
+
+// router returns the HTTP routes of the webhook server, queuing jobs into `queue`.
+pub fn router(secret: &str, queue: mpsc::Sender<Job>) -> Router {
+    Router::new()
+        .route("/webhook", post(webhook))
+        .with_state(AppState {
+            secret: secret.as_bytes().to_vec(),
+            queue,
+        })
+}
+
+async fn webhook(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let signature = headers
+        .get("X-Hub-Signature-256")
+        .and_then(|value| value.to_str().ok());
+    if !signature.is_some_and(|signature| verify_signature(&state.secret, &body, signature)) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    let Some(event_name) = headers
+        .get("X-GitHub-Event")
+        .and_then(|value| value.to_str().ok())
+    else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let payload = String::from_utf8_lossy(&body);
+    let target = match event::parse(event_name, &payload) {
+        Ok(Some(target)) => target,
+        // ping and other events that are not about a single issue
+        Ok(None) => return StatusCode::NO_CONTENT,
+        Err(err) => {
+            eprintln!("invalid {} event payload: {}", event_name, err);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    let Some(Ok(repo)) = target.repo.as_deref().map(str::parse::<Repo>) else {
+        eprintln!("{} event payload has no repository", event_name);
+        return StatusCode::BAD_REQUEST;
+    };
+    let job = Job {
+        repo,
+        issue_number: target.issue_number,
+        event_name: event_name.to_string(),
+    };
+    match state.queue.try_send(job) {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(err) => {
+            eprintln!("cannot queue event: {}", err);
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+// verify_signature returns true if the `sha256=<hex>` signature is the
+// HMAC-SHA256 of the body keyed with the secret.
+pub fn verify_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Some(Ok(signature)) = signature.strip_prefix("sha256=").map(hex::decode) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "It's a Secret to Everybody";
+
+    fn sign(body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        // example from the GitHub documentation
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify_signature(
+            SECRET.as_bytes(),
+            b"Hello, World!",
+            signature
+        ));
+        assert!(!verify_signature(b"other", b"Hello, World!", signature));
+        assert!(!verify_signature(SECRET.as_bytes(), b"Hello!", signature));
+        assert!(!verify_signature(
+            SECRET.as_bytes(),
+            b"Hello, World!",
+            "sha1=00"
+        ));
+        assert!(!verify_signature(
+            SECRET.as_bytes(),
+            b"Hello, World!",
+            "sha256=xyz"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let (queue, mut jobs) = mpsc::channel(1);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, router(SECRET, queue)).await.unwrap();
+        });
+        let http = reqwest::Client::new();
+        let post = |event: &str, body: &str, signature: String| {
+            http.post(&url)
+                .header("X-GitHub-Event", event)
+                .header("X-Hub-Signature-256", signature)
+                .body(body.to_string())
+                .send()
+        };
+
+        let payload = r#"
+        {
+            "action": "created",
+            "issue": { "number": 12, "title": "Issue 12" },
+            "comment": { "id": 1001, "body": "/in-progress" },
+            "repository": { "full_name": "yurishkuro/rustybot" }
+        }
+        "#;
+        let response = post("issue_comment", payload, sign(payload)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(
+            jobs.recv().await.unwrap(),
+            Job {
+                repo: "yurishkuro/rustybot".parse().unwrap(),
+                issue_number: 12,
+                event_name: "issue_comment".to_string(),
+            }
+        );
+
+        // invalid or missing signature
+        let response = post("issue_comment", payload, sign("{}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = http.post(&url).body(payload).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // events that are not about a single issue are acknowledged and ignored
+        let ping = r#"{"zen": "Keep it logically awesome."}"#;
+        let response = post("ping", ping, sign(ping)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // the queue is full
+        let response = post("issues", payload, sign(payload)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = post("issues", payload, sign(payload)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(jobs.recv().await.unwrap().event_name, "issues");
+        assert!(jobs.try_recv().is_err());
+    }
+}